@@ -0,0 +1,569 @@
+use crate::err_log;
+use crate::{
+    errors::ExportError,
+    scoring::{AnswerSheetResult, CheckedAnswer},
+    signal,
+    state::{AnswerKeySheet, AnswerSheet, AppState, CsvExport},
+    statistics::{mean, std_dev},
+};
+use itertools::Itertools;
+use log::info;
+use serde::Serialize;
+use std::{array, collections::HashMap, fs::File};
+use tauri::{ipc::Channel, Emitter, Manager, Runtime};
+use tauri_plugin_fs::FilePath;
+
+/// Fraction of students taken from the top and bottom of the score ranking when computing the
+/// upper-lower discrimination index. 27% is the usual choice (Kelley, 1939).
+const UPPER_LOWER_FRACTION: f64 = 0.27;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerdictCounts {
+    pub correct: u32,
+    pub incorrect: u32,
+    pub missing: u32,
+}
+impl VerdictCounts {
+    fn add(&mut self, verdict: CheckedAnswer) {
+        match verdict {
+            CheckedAnswer::Correct => self.correct += 1,
+            CheckedAnswer::Incorrect => self.incorrect += 1,
+            CheckedAnswer::Missing => self.missing += 1,
+            CheckedAnswer::NotCounted => (),
+        }
+    }
+    pub fn counted(&self) -> u32 {
+        self.correct + self.incorrect + self.missing
+    }
+    /// Proportion of counted answers that were correct, `None` if nothing was counted.
+    pub fn difficulty(&self) -> Option<f64> {
+        let counted = self.counted();
+        (counted != 0).then(|| self.correct as f64 / counted as f64)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowAnalysis {
+    #[serde(flatten)]
+    pub counts: VerdictCounts,
+    pub difficulty: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemAnalysis {
+    /// 1-based question number, as printed on the sheet.
+    pub question: u8,
+    #[serde(flatten)]
+    pub counts: VerdictCounts,
+    /// p-value: proportion of students that got the whole question right.
+    pub difficulty: Option<f64>,
+    /// Correlation between getting this question right and the total score.
+    pub point_biserial: Option<f64>,
+    /// Difference in difficulty between the upper and lower 27% of students.
+    pub discrimination: Option<f64>,
+    /// Statistics for rows A to E.
+    pub rows: [RowAnalysis; 5],
+}
+
+/// Flattened version of [`ItemAnalysis`] for CSV export.
+#[derive(Debug, Serialize)]
+struct ItemAnalysisRow {
+    question: u8,
+    counted: u32,
+    correct: u32,
+    incorrect: u32,
+    missing: u32,
+    difficulty: Option<f64>,
+    point_biserial: Option<f64>,
+    discrimination: Option<f64>,
+    a_difficulty: Option<f64>,
+    b_difficulty: Option<f64>,
+    c_difficulty: Option<f64>,
+    d_difficulty: Option<f64>,
+    e_difficulty: Option<f64>,
+}
+impl From<&ItemAnalysis> for ItemAnalysisRow {
+    fn from(item: &ItemAnalysis) -> Self {
+        let [a, b, c, d, e] = &item.rows;
+        Self {
+            question: item.question,
+            counted: item.counts.counted(),
+            correct: item.counts.correct,
+            incorrect: item.counts.incorrect,
+            missing: item.counts.missing,
+            difficulty: item.difficulty,
+            point_biserial: item.point_biserial,
+            discrimination: item.discrimination,
+            a_difficulty: a.difficulty,
+            b_difficulty: b.difficulty,
+            c_difficulty: c.difficulty,
+            d_difficulty: d.difficulty,
+            e_difficulty: e.difficulty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseCount {
+    /// The answer as written on the sheet, e.g. `7`, `-3` or `±2`.
+    pub response: String,
+    pub count: u32,
+    pub is_key: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DistractorAnalysis {
+    /// 1-based question number, as printed on the sheet.
+    pub question: u8,
+    pub row: char,
+    pub key: String,
+    /// Every distinct answer given on this row, most common first.
+    pub responses: Vec<ResponseCount>,
+    pub blank: u32,
+    pub multiple_marks: u32,
+}
+
+/// One line of the distractor table for CSV export.
+#[derive(Debug, Serialize)]
+struct DistractorRow<'a> {
+    question: u8,
+    row: char,
+    key: &'a str,
+    response: &'a str,
+    count: u32,
+    is_key: bool,
+}
+
+fn point_biserial(scores: &[(f64, bool)]) -> Option<f64> {
+    let (right, wrong): (Vec<f64>, Vec<f64>) = scores.iter().partition_map(|(s, correct)| {
+        if *correct {
+            itertools::Either::Left(*s)
+        } else {
+            itertools::Either::Right(*s)
+        }
+    });
+    let all = scores.iter().map(|(s, _)| *s).collect::<Vec<_>>();
+    let sd = std_dev(&all)?;
+    if sd == 0.0 {
+        return None;
+    }
+    let (mean_right, mean_wrong) = (mean(&right)?, mean(&wrong)?);
+    let p = right.len() as f64 / all.len() as f64;
+    Some((mean_right - mean_wrong) / sd * (p * (1.0 - p)).sqrt())
+}
+
+fn upper_lower_discrimination(scores: &[(f64, bool)]) -> Option<f64> {
+    if scores.len() < 2 {
+        return None;
+    }
+    let group = ((scores.len() as f64 * UPPER_LOWER_FRACTION).ceil() as usize).max(1);
+    let ranked = scores
+        .iter()
+        .sorted_by(|a, b| b.0.partial_cmp(&a.0).expect("not NaN"))
+        .collect::<Vec<_>>();
+    let correct_in = |g: &[&(f64, bool)]| g.iter().filter(|(_, c)| *c).count() as f64;
+    let upper = correct_in(&ranked[..group]);
+    let lower = correct_in(&ranked[ranked.len() - group..]);
+    Some((upper - lower) / group as f64)
+}
+
+pub fn analyze_items<'a, I: IntoIterator<Item = &'a AnswerSheetResult>>(
+    results: I,
+) -> Vec<ItemAnalysis> {
+    let results = results.into_iter().collect::<Vec<_>>();
+
+    (0..36)
+        .map(|q| {
+            let mut counts = VerdictCounts::default();
+            let mut row_counts: [VerdictCounts; 5] = array::from_fn(|_| VerdictCounts::default());
+            let mut scores: Vec<(f64, bool)> = vec![];
+
+            for result in &results {
+                let (checked, _) = &result.graded_questions[q];
+                let verdict = checked.verdict();
+                counts.add(verdict);
+                if verdict != CheckedAnswer::NotCounted {
+                    scores.push((result.score as f64, verdict == CheckedAnswer::Correct));
+                }
+                for (idx, row) in row_counts.iter_mut().enumerate() {
+                    row.add(checked.at(idx).expect("checked answer < 5"));
+                }
+            }
+
+            ItemAnalysis {
+                question: q as u8 + 1,
+                difficulty: counts.difficulty(),
+                point_biserial: point_biserial(&scores),
+                discrimination: upper_lower_discrimination(&scores),
+                counts,
+                rows: row_counts.map(|counts| RowAnalysis {
+                    difficulty: counts.difficulty(),
+                    counts,
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Counts the answers chosen on every row the key has an answer for.
+pub fn analyze_distractors<'a, I: IntoIterator<Item = &'a AnswerSheet>>(
+    key: &AnswerKeySheet,
+    sheets: I,
+) -> Vec<DistractorAnalysis> {
+    let sheets = sheets.into_iter().collect::<Vec<_>>();
+
+    key.answers
+        .iter()
+        .enumerate()
+        .flat_map(|(q, key_group)| {
+            key_group
+                .rows()
+                .into_iter()
+                .zip('A'..='E')
+                .enumerate()
+                .filter_map(|(r, (key_answer, row))| key_answer.map(|k| (q, r, row, k)))
+                .collect::<Vec<_>>()
+        })
+        .map(|(q, r, row, key_answer)| {
+            let key = key_answer.to_string();
+            let (mut blank, mut multiple_marks) = (0u32, 0u32);
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for sheet in &sheets {
+                if sheet.multi_marked[q][r] {
+                    multiple_marks += 1;
+                } else if let Some(answer) = sheet.answers[q].rows()[r] {
+                    *counts.entry(answer.to_string()).or_default() += 1;
+                } else {
+                    blank += 1;
+                }
+            }
+            let responses = counts
+                .into_iter()
+                .sorted_by(|(a_res, a_count), (b_res, b_count)| {
+                    b_count.cmp(a_count).then_with(|| a_res.cmp(b_res))
+                })
+                .map(|(response, count)| ResponseCount {
+                    is_key: response == key,
+                    response,
+                    count,
+                })
+                .collect();
+
+            DistractorAnalysis {
+                question: q as u8 + 1,
+                row,
+                key,
+                responses,
+                blank,
+                multiple_marks,
+            }
+        })
+        .collect()
+}
+
+fn distractor_rows(analysis: &DistractorAnalysis) -> Vec<DistractorRow<'_>> {
+    analysis
+        .responses
+        .iter()
+        .map(|r| (r.response.as_str(), r.count, r.is_key))
+        .chain([
+            ("blank", analysis.blank, false),
+            ("multiple", analysis.multiple_marks, false),
+        ])
+        .map(|(response, count, is_key)| DistractorRow {
+            question: analysis.question,
+            row: analysis.row,
+            key: &analysis.key,
+            response,
+            count,
+            is_key,
+        })
+        .collect()
+}
+
+pub fn item_analysis_of<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+) -> Result<Vec<ItemAnalysis>, ExportError> {
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    Ok(analyze_items(results.values().map(|(_, _, r)| r)))
+}
+
+pub fn distractor_analysis_of<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+) -> Result<Vec<DistractorAnalysis>, ExportError> {
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let key = AppState::get_key(app).ok_or(ExportError::IncorrectState)?;
+    Ok(analyze_distractors(
+        &key,
+        results.values().map(|(_, sheet, _)| sheet),
+    ))
+}
+
+pub fn export_item_analysis_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    match export_item_analysis_impl(app, path) {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+pub fn export_item_analysis_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+) -> Result<(), ExportError> {
+    let path = path.into_path()?;
+    let analysis = item_analysis_of(app)?;
+
+    info!("Exporting item analysis to {}...", path.display());
+    let mut wtr = csv::Writer::from_writer(File::create(path)?);
+    for item in &analysis {
+        wtr.serialize(ItemAnalysisRow::from(item))?;
+    }
+    wtr.flush()?;
+    info!("Finished exporting item analysis!");
+
+    Ok(())
+}
+
+pub fn export_distractor_analysis_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    match export_distractor_analysis_impl(app, path) {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+pub fn export_distractor_analysis_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+) -> Result<(), ExportError> {
+    let path = path.into_path()?;
+    let analysis = distractor_analysis_of(app)?;
+
+    info!("Exporting distractor analysis to {}...", path.display());
+    let mut wtr = csv::Writer::from_writer(File::create(path)?);
+    for row in analysis.iter().flat_map(distractor_rows) {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    info!("Finished exporting distractor analysis!");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::{
+        scoring::CheckedQuestionGroup,
+        state::{unit_tests::SheetBuilder, Answer, NumberType, QuestionGroup},
+    };
+
+    fn group(verdict: CheckedAnswer) -> CheckedQuestionGroup {
+        CheckedQuestionGroup {
+            A: verdict,
+            B: verdict,
+            C: verdict,
+            D: verdict,
+            E: CheckedAnswer::NotCounted,
+        }
+    }
+
+    /// A result where the first `right` questions are correct and the rest are incorrect.
+    fn result_with(right: usize) -> AnswerSheetResult {
+        AnswerSheetResult {
+            correct: right as u32,
+            incorrect: 36 - right as u32,
+            score: right as u32,
+            graded_questions: array::from_fn(|q| {
+                if q < right {
+                    (group(CheckedAnswer::Correct), 1)
+                } else {
+                    (group(CheckedAnswer::Incorrect), 0)
+                }
+            }),
+        }
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let results = [result_with(10), result_with(20), result_with(30)];
+        let items = analyze_items(&results);
+
+        assert_eq!(items.len(), 36);
+        assert_eq!(items[0].question, 1);
+        assert_eq!(items[0].difficulty, Some(1.0));
+        assert_eq!(items[15].counts.correct, 2);
+        assert_eq!(items[15].counts.incorrect, 1);
+        assert!((items[15].difficulty.unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(items[35].difficulty, Some(0.0));
+    }
+
+    #[test]
+    fn test_row_statistics() {
+        let results = [result_with(10), result_with(20)];
+        let items = analyze_items(&results);
+
+        let [a, _, _, d, e] = &items[15].rows;
+        assert_eq!(a.difficulty, Some(0.5));
+        assert_eq!(d.counts.correct, 1);
+        assert_eq!(e.counts.counted(), 0);
+        assert_eq!(e.difficulty, None);
+    }
+
+    #[test]
+    fn test_discrimination() {
+        let results = [
+            result_with(5),
+            result_with(10),
+            result_with(20),
+            result_with(30),
+        ];
+        let items = analyze_items(&results);
+
+        // everyone got question 1 right, nobody got question 36 right
+        assert_eq!(items[0].discrimination, Some(0.0));
+        assert_eq!(items[35].discrimination, Some(0.0));
+        assert_eq!(items[0].point_biserial, None);
+        // only the top students got question 25 right
+        assert_eq!(items[24].discrimination, Some(0.5));
+        assert!(items[24].point_biserial.unwrap() > 0.5);
+    }
+
+    #[test]
+    fn test_not_counted_question() {
+        let mut result = result_with(36);
+        result.graded_questions[0] = (group(CheckedAnswer::NotCounted), 0);
+        let items = analyze_items([&result]);
+
+        assert_eq!(items[0].counts.counted(), 0);
+        assert_eq!(items[0].difficulty, None);
+        assert_eq!(items[0].discrimination, None);
+    }
+
+    #[test]
+    fn test_item_analysis_csv() {
+        let items = analyze_items(&[result_with(1)]);
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(ItemAnalysisRow::from(&items[0])).unwrap();
+
+        let result = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            result,
+            "question,counted,correct,incorrect,missing,difficulty,point_biserial,discrimination,a_difficulty,b_difficulty,c_difficulty,d_difficulty,e_difficulty
+1,1,1,0,0,1.0,,,1.0,1.0,1.0,1.0,
+"
+        )
+    }
+
+    fn sheet_answering(a: Option<Answer>, multi_marked: bool) -> AnswerSheet {
+        let mut marks = [[false; 5]; 36];
+        marks[0][0] = multi_marked;
+        SheetBuilder::default()
+            .answers(array::from_fn(|_| QuestionGroup {
+                A: a,
+                ..Default::default()
+            }))
+            .multi_marked(marks)
+            .build()
+    }
+
+    #[test]
+    fn test_distractors() {
+        let key = AnswerKeySheet {
+            subject_id: "10".to_string(),
+            answers: array::from_fn(|q| QuestionGroup {
+                A: (q == 0).then_some(Answer::Both(NumberType::Minus, 3)),
+                ..Default::default()
+            }),
+        };
+        let sheets = [
+            sheet_answering(Some(Answer::Both(NumberType::Minus, 3)), false),
+            sheet_answering(Some(Answer::Number(3)), false),
+            sheet_answering(Some(Answer::Number(3)), false),
+            sheet_answering(None, false),
+            sheet_answering(None, true),
+        ];
+        let analysis = analyze_distractors(&key, &sheets);
+
+        // only rows with a key are reported
+        assert_eq!(analysis.len(), 1);
+        let row = &analysis[0];
+        assert_eq!(row.question, 1);
+        assert_eq!(row.row, 'A');
+        assert_eq!(row.key, "-3");
+        assert_eq!(row.blank, 1);
+        assert_eq!(row.multiple_marks, 1);
+        assert_eq!(row.responses.len(), 2);
+        assert_eq!(row.responses[0].response, "3");
+        assert_eq!(row.responses[0].count, 2);
+        assert!(!row.responses[0].is_key);
+        assert_eq!(row.responses[1].response, "-3");
+        assert!(row.responses[1].is_key);
+    }
+
+    #[test]
+    fn test_distractor_csv() {
+        let analysis = DistractorAnalysis {
+            question: 4,
+            row: 'B',
+            key: "±2".to_string(),
+            responses: vec![ResponseCount {
+                response: "±2".to_string(),
+                count: 3,
+                is_key: true,
+            }],
+            blank: 1,
+            multiple_marks: 0,
+        };
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for row in distractor_rows(&analysis) {
+            writer.serialize(row).unwrap();
+        }
+
+        let result = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            result,
+            "question,row,key,response,count,is_key
+4,B,±2,±2,3,true
+4,B,±2,blank,1,false
+4,B,±2,multiple,0,false
+"
+        )
+    }
+}
@@ -1,17 +1,27 @@
-#[cfg(not(feature = "compile-tesseract"))]
-use crate::errors::OcrError;
 use crate::{
-    download::{self, ModelDownload},
+    analysis::{self, DistractorAnalysis, ItemAnalysis},
+    download::{self, InstalledModel, ModelDownload, ModelInstall},
     err_log,
     errors::ModelDownloadError,
-    image::{upload_key_image_impl, upload_sheet_images_impl},
-    ocr::OcrEngine,
+    gradebook::{self, GradebookExport},
+    grading::GradingScale,
+    image::{self, upload_key_image_impl, upload_sheet_images_impl, ImageExportOptions},
+    ocr::{OcrBackendKind, OcrLanguages},
+    reconcile::IdReview,
+    report, roster,
     scoring::upload_weights_impl,
-    state::{AnswerUpload, CsvExport, KeyUpload, LoginRequest, LoginResponse},
-    storage, AppState,
+    settings,
+    state::{
+        AnswerUpload, CsvExport, DbSync, KeyUpload, LoginRequest, LoginResponse, Options,
+        RosterImport, Storage, MODELS,
+    },
+    statistics::{self, ExamStatistics},
+    storage::{self, HistoryEntry, SessionSummary, SyncCounts},
+    AppState,
 };
 use log::{debug, info};
 use reqwest::Client;
+use std::path::PathBuf;
 
 use tauri::{ipc::Channel, WebviewWindowBuilder};
 use tauri::{AppHandle, Manager};
@@ -84,27 +94,56 @@ pub fn clear_sheet_images(app: AppHandle, channel: Channel<AnswerUpload>) {
     AppState::clear_answer_sheets(&app, &channel);
 }
 
+#[tauri::command]
+pub fn import_roster(app: AppHandle, channel: Channel<RosterImport>) {
+    info!("Importing roster");
+    app.dialog()
+        .file()
+        .add_filter("Comma Seperated Value files (*.csv)", &["csv"])
+        .pick_file(move |file_path| {
+            roster::import_roster_wrapper(&app, file_path, channel);
+        });
+}
+
 #[tauri::command]
 pub fn set_ocr(app: AppHandle, ocr: bool) -> Result<(), String> {
-    let has_tess = OcrEngine::check_tesseract().map_err(|e| {
+    let Options { ocr_backend, .. } = AppState::get_options(&app);
+    ocr_backend.check_available().map_err(|e| {
         err_log!(&e);
         format!("{e}")
     })?;
-    if has_tess {
-        debug!("Set ocr = {ocr}");
-        AppState::set_ocr(&app, ocr);
-        Ok(())
-    } else {
-        #[cfg(not(feature = "compile-tesseract"))]
-        {
-            Err(format!("{}", OcrError::NoTesseract))
-        }
-        #[cfg(feature = "compile-tesseract")]
-        {
-            // with `compile-tesseract`, `OcrEngine::check_tesseract()` always returns true
-            unreachable!()
-        }
-    }
+    debug!("Set ocr = {ocr}");
+    AppState::set_ocr(&app, ocr);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_ocr_backend(app: AppHandle, backend: OcrBackendKind) -> Result<(), String> {
+    backend.check_available().map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })?;
+    debug!("Set OCR backend = {backend:?}");
+    AppState::set_ocr_backend(&app, backend);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_model_mirrors(app: AppHandle, mirrors: Vec<String>) -> Result<(), String> {
+    download::validate_mirrors(&mirrors).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })?;
+    debug!("Set model mirrors = {mirrors:?}");
+    AppState::set_model_mirrors(&app, mirrors);
+    Ok(())
+}
+
+/// Lets models without a pinned hash be used. Their hash is recorded on first download instead.
+#[tauri::command]
+pub fn set_allow_unpinned_models(app: AppHandle, allow: bool) {
+    debug!("Set allow unpinned models = {allow}");
+    AppState::set_allow_unpinned_models(&app, allow);
 }
 
 #[tauri::command(async)]
@@ -116,6 +155,83 @@ pub async fn ensure_models(
     download::get_or_download_models(app, channel).await
 }
 
+/// Keeps models in `dir`, or in the OS cache directory if `None`. Gives the directory that is
+/// used, as `QUIKSCORE_MODEL_DIR` overrides the setting.
+#[tauri::command]
+pub fn set_model_dir(app: AppHandle, dir: Option<PathBuf>) -> Result<String, String> {
+    let dir = dir.filter(|dir| !dir.as_os_str().is_empty());
+    let used = download::model_dir(dir.as_deref()).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })?;
+    debug!("Set model dir = {dir:?}, using {}", used.display());
+    AppState::set_model_dir(&app, dir.clone());
+    *MODELS.write().expect("poisoned") = None;
+    settings::save_model_dir(&app, dir.as_deref()).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })?;
+    Ok(used.display().to_string())
+}
+
+#[tauri::command(async)]
+pub fn list_models(app: AppHandle) -> Result<Vec<InstalledModel>, String> {
+    info!("Listing OCR models");
+    download::list_models(&app, false).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command(async)]
+pub fn verify_models(app: AppHandle) -> Result<Vec<InstalledModel>, String> {
+    info!("Verifying OCR models");
+    download::list_models(&app, true).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub fn delete_models(app: AppHandle, names: Vec<String>) -> Result<(), String> {
+    info!("Deleting OCR models {names:?}");
+    download::delete_models_of(&app, &names).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub fn install_models(app: AppHandle, from_folder: bool, channel: Channel<ModelInstall>) {
+    info!("Installing OCR models from a local bundle");
+    let dialog = app.dialog().file();
+    if from_folder {
+        dialog.pick_folder(move |folder_path| {
+            download::install_models_wrapper(&app, folder_path, channel);
+        });
+    } else {
+        dialog
+            .add_filter(
+                "Model bundles and files",
+                &["zip", "traineddata", "rten", "onnx"],
+            )
+            .pick_file(move |file_path| {
+                download::install_models_wrapper(&app, file_path, channel);
+            });
+    }
+}
+
+#[tauri::command]
+pub fn export_model_bundle(app: AppHandle, channel: Channel<CsvExport>) {
+    info!("Exporting OCR models");
+    app.dialog()
+        .file()
+        .add_filter("Model bundles (*.zip)", &["zip"])
+        .save_file(move |file_path| {
+            download::export_model_bundle_wrapper(&app, file_path, channel);
+        });
+}
+
 #[tauri::command]
 pub fn export_csv(app: AppHandle, channel: Channel<CsvExport>) {
     info!("Exporting results");
@@ -128,9 +244,229 @@ pub fn export_csv(app: AppHandle, channel: Channel<CsvExport>) {
 }
 
 #[tauri::command]
-pub fn enter_database_information(app: AppHandle, uri: String, name: String) {
+pub fn export_xlsx(app: AppHandle, channel: Channel<CsvExport>) {
+    info!("Exporting results as XLSX");
+    app.dialog()
+        .file()
+        .add_filter("Excel Workbook (*.xlsx)", &["xlsx"])
+        .save_file(move |file_path| {
+            storage::export_to_xlsx_wrapper(&app, file_path, channel);
+        });
+}
+
+#[tauri::command]
+pub fn export_json(app: AppHandle, channel: Channel<CsvExport>) {
+    info!("Exporting results as JSON");
+    app.dialog()
+        .file()
+        .add_filter("JSON files (*.json)", &["json"])
+        .add_filter("JSON Lines files (*.jsonl)", &["jsonl"])
+        .save_file(move |file_path| {
+            storage::export_to_json_wrapper(&app, file_path, channel);
+        });
+}
+
+#[tauri::command]
+pub fn export_pdf_reports(app: AppHandle, per_student: bool, channel: Channel<CsvExport>) {
+    info!("Exporting PDF score reports");
+    let dialog = app.dialog().file();
+    if per_student {
+        dialog.pick_folder(move |folder_path| {
+            report::export_pdf_reports_wrapper(&app, folder_path, true, channel);
+        });
+    } else {
+        dialog
+            .add_filter("PDF files (*.pdf)", &["pdf"])
+            .save_file(move |file_path| {
+                report::export_pdf_reports_wrapper(&app, file_path, false, channel);
+            });
+    }
+}
+
+#[tauri::command]
+pub fn export_gradebook(app: AppHandle, export: GradebookExport, channel: Channel<CsvExport>) {
+    info!("Exporting {:?} gradebook", export.profile);
+    app.dialog()
+        .file()
+        .add_filter("Comma Seperated Value files (*.csv)", &["csv"])
+        .save_file(move |file_path| {
+            gradebook::export_gradebook_wrapper(&app, file_path, export, channel);
+        });
+}
+
+#[tauri::command]
+pub fn export_images(app: AppHandle, options: ImageExportOptions, channel: Channel<CsvExport>) {
+    info!("Exporting annotated images");
+    app.dialog().file().pick_folder(move |folder_path| {
+        image::export_images_wrapper(&app, folder_path, options, channel);
+    });
+}
+
+#[tauri::command]
+pub fn item_analysis(app: AppHandle) -> Result<Vec<ItemAnalysis>, String> {
+    info!("Computing item analysis");
+    analysis::item_analysis_of(&app).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub fn export_item_analysis(app: AppHandle, channel: Channel<CsvExport>) {
+    info!("Exporting item analysis");
+    app.dialog()
+        .file()
+        .add_filter("Comma Seperated Value files (*.csv)", &["csv"])
+        .save_file(move |file_path| {
+            analysis::export_item_analysis_wrapper(&app, file_path, channel);
+        });
+}
+
+#[tauri::command]
+pub fn distractor_analysis(app: AppHandle) -> Result<Vec<DistractorAnalysis>, String> {
+    info!("Computing distractor analysis");
+    analysis::distractor_analysis_of(&app).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub fn export_distractor_analysis(app: AppHandle, channel: Channel<CsvExport>) {
+    info!("Exporting distractor analysis");
+    app.dialog()
+        .file()
+        .add_filter("Comma Seperated Value files (*.csv)", &["csv"])
+        .save_file(move |file_path| {
+            analysis::export_distractor_analysis_wrapper(&app, file_path, channel);
+        });
+}
+
+#[tauri::command]
+pub fn exam_statistics(app: AppHandle) -> Result<ExamStatistics, String> {
+    info!("Computing exam statistics");
+    statistics::exam_statistics_of(&app).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub fn set_ocr_languages(app: AppHandle, languages: OcrLanguages) -> Result<(), String> {
+    languages.validate().map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })?;
+    debug!("Set OCR languages = {languages:?}");
+    AppState::set_ocr_languages(&app, languages);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_grading_scale(app: AppHandle, scale: Option<GradingScale>) -> Result<(), String> {
+    if let Some(scale) = &scale {
+        scale.validate().map_err(|e| {
+            err_log!(&e);
+            format!("{e}")
+        })?;
+    }
+    debug!("Set grading scale = {scale:?}");
+    AppState::set_grading(&app, scale);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn enter_database_information(app: AppHandle, uri: String, name: String) -> Result<(), String> {
     info!("Enter Database Information");
-    AppState::set_mongodb(&app, uri, name);
+    set_storage(
+        app,
+        Storage::MongoDb {
+            mongo_db_uri: uri,
+            mongo_db_name: name,
+        },
+    )
+}
+
+#[tauri::command]
+pub fn set_storage(app: AppHandle, storage: Storage) -> Result<(), String> {
+    info!("Set storage backend");
+    AppState::set_storage(&app, storage.clone());
+    settings::save_storage(&app, &storage).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub async fn test_database_connection(storage: Storage) -> Result<(), String> {
+    info!("Testing database connection");
+    storage::test_connection(&storage).await.map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub async fn sync_database(
+    app: AppHandle,
+    exam: String,
+    dry_run: bool,
+    include_details: bool,
+    channel: Channel<DbSync>,
+) {
+    info!("Syncing scores to database");
+    storage::sync_database_wrapper(&app, exam, dry_run, include_details, channel).await;
+}
+
+#[tauri::command]
+pub async fn retry_database_sync(app: AppHandle) -> Result<SyncCounts, String> {
+    info!("Retrying queued database sync");
+    storage::retry_sync_queue(&app).await.map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub fn queued_sync_rows(app: AppHandle) -> Result<usize, String> {
+    storage::SyncQueue::open_default(&app)
+        .and_then(|queue| queue.queued_rows())
+        .map_err(|e| {
+            err_log!(&e);
+            format!("{e}")
+        })
+}
+
+#[tauri::command]
+pub async fn save_history(app: AppHandle, exam: String) -> Result<i64, String> {
+    info!("Saving results to history");
+    storage::save_to_history(&app, exam).await.map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub async fn history_sessions(app: AppHandle) -> Result<Vec<SessionSummary>, String> {
+    info!("Listing results history");
+    storage::history_sessions(&app).await.map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
+#[tauri::command]
+pub async fn student_history(
+    app: AppHandle,
+    student_id: String,
+) -> Result<Vec<HistoryEntry>, String> {
+    info!("Looking up history of student {student_id}");
+    storage::student_history(&app, student_id)
+        .await
+        .map_err(|e| {
+            err_log!(&e);
+            format!("{e}")
+        })
 }
 
 #[tauri::command]
@@ -176,6 +512,23 @@ pub async fn login(app: AppHandle, username: String, password: String) -> Result
     }
 }
 
+#[tauri::command]
+pub fn id_reviews(app: AppHandle) -> Vec<IdReview> {
+    AppState::id_reviews(&app)
+}
+
+#[tauri::command]
+pub fn resolve_id_review(
+    app: AppHandle,
+    student_id: String,
+    resolved_id: String,
+) -> Result<(), String> {
+    AppState::resolve_id_review(&app, &student_id, resolved_id).map_err(|e| {
+        err_log!(&e);
+        format!("{e}")
+    })
+}
+
 #[tauri::command]
 pub fn image_of(app: AppHandle, id: String) -> Option<Vec<u8>> {
     AppState::get_base64_for_id(&app, id)
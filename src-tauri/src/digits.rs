@@ -0,0 +1,288 @@
+use crate::{
+    errors::OcrError,
+    image::roi_range_frac,
+    ocr::{OcrBackend, OcrField, OcrText, MIN_CONFIDENCE},
+    reconcile::STUDENT_ID_LEN,
+};
+use opencv::{
+    core::{self, Rect, Scalar, Size, CV_32F, CV_8UC1},
+    dnn::{self, Net},
+    imgproc,
+    prelude::*,
+};
+use std::{cell::RefCell, path::Path};
+
+/// File name of the digit classifier in the model directory. This is the MNIST model from the
+/// ONNX model zoo, which takes a 1x1x28x28 image of a white digit on black scaled to 0..1, and
+/// gives a score for each digit.
+pub const DIGIT_MODEL: &str = "mnist-12.onnx";
+const MNIST_SIZE: i32 = 28;
+/// MNIST digits are fit into a 20x20 box in the middle of the image.
+const MNIST_DIGIT_SIZE: i32 = 20;
+/// Part of each side of a box that is cut off so its printed border isn't read as ink.
+const BOX_MARGIN: f64 = 0.12;
+/// Boxes with less ink than this are taken to be empty.
+const MIN_INK: f64 = 0.02;
+/// Stands in for a character that wasn't read, such as the digit of an empty box, so the ones
+/// after it keep their position.
+pub const EMPTY_BOX: char = '?';
+
+/// Number of digit boxes in `field`, if it is an ID field.
+pub fn digit_boxes(field: OcrField) -> Option<usize> {
+    match field {
+        OcrField::SubjectId => Some(3),
+        OcrField::StudentId => Some(STUDENT_ID_LEN),
+        _ => None,
+    }
+}
+
+/// A small CNN that recognises one handwritten digit at a time. It runs on OpenCV's dnn module,
+/// which is linked in anyway, since rten only loads its own format and not ONNX.
+pub struct DigitClassifier {
+    /// Running the network needs it mutably.
+    net: RefCell<Net>,
+}
+
+impl DigitClassifier {
+    pub fn load(path: &Path) -> Result<DigitClassifier, OcrError> {
+        let path = path.to_str().ok_or_else(|| {
+            OcrError::DigitModel(opencv::Error::new(
+                core::StsBadArg,
+                "model path is not unicode",
+            ))
+        })?;
+        let net = dnn::read_net_from_onnx(path).map_err(OcrError::DigitModel)?;
+        Ok(DigitClassifier {
+            net: RefCell::new(net),
+        })
+    }
+
+    /// Reads a row of `boxes` equally wide digit boxes, see [`read_boxes`].
+    pub fn classify(&self, mat: &Mat, boxes: usize) -> Result<OcrText, OcrError> {
+        read_boxes(mat, boxes, |input| self.classify_digit(input))
+    }
+
+    /// The most likely digit in a [`mnist_input`] image and its probability.
+    fn classify_digit(&self, input: &Mat) -> Result<(u8, f32), OcrError> {
+        let blob = dnn::blob_from_image(
+            input,
+            1.0 / 255.0,
+            Size::new(MNIST_SIZE, MNIST_SIZE),
+            Scalar::default(),
+            false,
+            false,
+            CV_32F,
+        )
+        .map_err(OcrError::DigitModel)?;
+        let mut net = self.net.borrow_mut();
+        net.set_input_def(&blob).map_err(OcrError::DigitModel)?;
+        let output = net.forward_single_def().map_err(OcrError::DigitModel)?;
+        let scores = output.data_typed::<f32>().map_err(OcrError::DigitModel)?;
+        Ok(best_digit(scores))
+    }
+}
+
+/// Reads a row of `boxes` equally wide digit boxes with `classify_digit`. Empty boxes are read as
+/// [`EMPTY_BOX`], and the confidence is the mean probability of the digits that were read, so a
+/// partly filled ID keeps its digits in place instead of losing to a read that shifts them.
+fn read_boxes(
+    mat: &Mat,
+    boxes: usize,
+    mut classify_digit: impl FnMut(&Mat) -> Result<(u8, f32), OcrError>,
+) -> Result<OcrText, OcrError> {
+    let mut text = String::new();
+    let mut probabilities = vec![];
+    for idx in 0..boxes {
+        let from = (idx as f64 + BOX_MARGIN) / boxes as f64;
+        let to = (idx as f64 + 1.0 - BOX_MARGIN) / boxes as f64;
+        let digit_box = roi_range_frac(mat, from..=to, BOX_MARGIN..=1.0 - BOX_MARGIN)?;
+        let Some(input) = mnist_input(&digit_box)? else {
+            text.push(EMPTY_BOX);
+            continue;
+        };
+        let (digit, probability) = classify_digit(&input)?;
+        text.push(char::from(b'0' + digit));
+        probabilities.push(probability);
+    }
+    let confidence = if probabilities.is_empty() {
+        0.0
+    } else {
+        probabilities.iter().sum::<f32>() / probabilities.len() as f32
+    };
+    Ok(OcrText {
+        text,
+        confidence: Some(confidence),
+    })
+}
+
+/// Softmax of the model's scores, giving the best digit and its probability.
+fn best_digit(scores: &[f32]) -> (u8, f32) {
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps = scores.iter().map(|s| (s - max).exp()).collect::<Vec<_>>();
+    let total = exps.iter().sum::<f32>();
+    exps.iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(digit, exp)| (digit as u8, exp / total))
+        .unwrap_or_default()
+}
+
+/// Turns a digit box into a 28x28 MNIST style image, or `None` if the box is empty.
+fn mnist_input(digit_box: &Mat) -> Result<Option<Mat>, OcrError> {
+    let mut gray = digit_box.clone();
+    if digit_box.channels() > 1 {
+        imgproc::cvt_color_def(digit_box, &mut gray, imgproc::COLOR_RGB2GRAY)?;
+    }
+    // ink becomes white, like in MNIST
+    let mut ink = Mat::default();
+    imgproc::threshold(&gray, &mut ink, 165.0, 255.0, imgproc::THRESH_BINARY_INV)?;
+    let area = (ink.rows() * ink.cols()) as f64;
+    if area == 0.0 || core::count_non_zero(&ink)? as f64 / area < MIN_INK {
+        return Ok(None);
+    }
+
+    let bounds = imgproc::bounding_rect(&ink)?;
+    let digit = Mat::roi(&ink, bounds)?;
+    let scale = MNIST_DIGIT_SIZE as f64 / bounds.width.max(bounds.height) as f64;
+    let size = Size::new(
+        ((bounds.width as f64 * scale).round() as i32).clamp(1, MNIST_DIGIT_SIZE),
+        ((bounds.height as f64 * scale).round() as i32).clamp(1, MNIST_DIGIT_SIZE),
+    );
+    let mut resized = Mat::default();
+    imgproc::resize(&digit, &mut resized, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+
+    let mut input =
+        Mat::new_rows_cols_with_default(MNIST_SIZE, MNIST_SIZE, CV_8UC1, Scalar::all(0.0))?;
+    let centered = Rect::new(
+        (MNIST_SIZE - size.width) / 2,
+        (MNIST_SIZE - size.height) / 2,
+        size.width,
+        size.height,
+    );
+    resized.copy_to(&mut Mat::roi_mut(&mut input, centered)?)?;
+    Ok(Some(input))
+}
+
+/// Reads the ID boxes with a [`DigitClassifier`], and everything else with `inner`. The ID
+/// boxes are read by `inner` too when the classifier isn't sure, and the more confident read is
+/// kept.
+pub struct WithDigitClassifier {
+    pub inner: Box<dyn OcrBackend>,
+    pub digits: DigitClassifier,
+}
+
+impl OcrBackend for WithDigitClassifier {
+    fn get_text(&self, mat: &Mat, field: OcrField) -> Result<OcrText, OcrError> {
+        let Some(boxes) = digit_boxes(field) else {
+            return self.inner.get_text(mat, field);
+        };
+        let classified = self.digits.classify(mat, boxes)?;
+        if classified.confidence.is_some_and(|c| c >= MIN_CONFIDENCE) {
+            return Ok(classified);
+        }
+        Ok(more_confident(classified, self.inner.get_text(mat, field)?))
+    }
+}
+
+/// `read` if its measured confidence beats that of `classified`. A read without a confidence
+/// can't be compared, so it never wins.
+fn more_confident(classified: OcrText, read: OcrText) -> OcrText {
+    match (classified.confidence, read.confidence) {
+        (Some(c), Some(r)) if r > c => read,
+        (None, Some(_)) => read,
+        _ => classified,
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn white_box() -> Mat {
+        Mat::new_rows_cols_with_default(60, 40, CV_8UC1, Scalar::all(255.0)).unwrap()
+    }
+
+    #[test]
+    fn test_mnist_input() {
+        assert!(mnist_input(&white_box()).unwrap().is_none());
+
+        // a tall stroke, like a 1
+        let mut digit_box = white_box();
+        imgproc::rectangle(
+            &mut digit_box,
+            Rect::new(18, 10, 6, 40),
+            Scalar::all(0.0),
+            imgproc::FILLED,
+            imgproc::LINE_8,
+            0,
+        )
+        .unwrap();
+        let input = mnist_input(&digit_box).unwrap().unwrap();
+        assert_eq!((input.rows(), input.cols()), (MNIST_SIZE, MNIST_SIZE));
+        let bounds = imgproc::bounding_rect(&input).unwrap();
+        assert_eq!(bounds.height, MNIST_DIGIT_SIZE);
+        assert_eq!(bounds.y, (MNIST_SIZE - MNIST_DIGIT_SIZE) / 2);
+        assert!((bounds.x + bounds.width / 2 - MNIST_SIZE / 2).abs() <= 1);
+    }
+
+    #[test]
+    fn test_empty_box() {
+        // three boxes with a stroke in the first and last
+        let mut row =
+            Mat::new_rows_cols_with_default(60, 120, CV_8UC1, Scalar::all(255.0)).unwrap();
+        for x in [18, 98] {
+            imgproc::rectangle(
+                &mut row,
+                Rect::new(x, 10, 6, 40),
+                Scalar::all(0.0),
+                imgproc::FILLED,
+                imgproc::LINE_8,
+                0,
+            )
+            .unwrap();
+        }
+        let read = read_boxes(&row, 3, |_| Ok((1, 0.9))).unwrap();
+        assert_eq!(read.text, "1?1");
+        assert!((read.confidence.unwrap() - 0.9).abs() < 1e-6);
+        let read = read_boxes(&white_box(), 1, |_| Ok((1, 0.9))).unwrap();
+        assert_eq!(read.text, "?");
+        assert_eq!(read.confidence, Some(0.0));
+
+        let read = read_boxes(&row, 2, |_| Ok((1, 0.9))).unwrap();
+        assert_eq!(read.text, "11");
+        assert!((read.confidence.unwrap() - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_more_confident() {
+        let text = |text: &str, confidence| OcrText {
+            text: text.to_string(),
+            confidence,
+        };
+        let classified = text("65010003", Some(0.4));
+        assert_eq!(
+            more_confident(classified.clone(), text("65010008", Some(0.9))).text,
+            "65010008"
+        );
+        assert_eq!(
+            more_confident(classified.clone(), text("65010008", Some(0.3))).text,
+            "65010003"
+        );
+        // ocrs doesn't measure confidence, which mustn't beat a measured one
+        assert_eq!(
+            more_confident(classified, text("65010008", None)).text,
+            "65010003"
+        );
+    }
+
+    #[test]
+    fn test_best_digit() {
+        let mut scores = [0.0; 10];
+        scores[7] = 5.0;
+        let (digit, probability) = best_digit(&scores);
+        assert_eq!(digit, 7);
+        assert!(probability > 0.9);
+        assert_eq!(digit_boxes(OcrField::StudentId), Some(STUDENT_ID_LEN));
+        assert_eq!(digit_boxes(OcrField::StudentName), None);
+    }
+}
@@ -1,150 +1,387 @@
-use crate::err_log;
 use crate::{
+    digits::DIGIT_MODEL,
+    err_log,
     errors::ModelDownloadError,
+    ocr::{OcrBackendKind, OcrLanguages, DETECTION_MODEL, RECOGNITION_MODEL},
     signal,
-    state::{AppState, Options, MODELS},
+    state::{AppState, CsvExport, Options, MODELS},
 };
 use futures::StreamExt;
 use log::{info, warn};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::fs::File;
-use tauri::{ipc::Channel, AppHandle};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+use tauri::{ipc::Channel, AppHandle, Emitter, Manager, Runtime};
+use tauri_plugin_fs::FilePath;
 use tokio::io::AsyncWriteExt;
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
 
-const ENG_TESSDATA: &str =
-    "https://raw.githubusercontent.com/tesseract-ocr/tessdata_best/refs/heads/main/eng.traineddata";
-const ENG_TESSDATA_HASH: [u8; 32] =
-    hex_literal::hex!("8280aed0782fe27257a68ea10fe7ef324ca0f8d85bd2fd145d1c2b560bcb66ba");
+const TESSDATA_URL: &str =
+    "https://raw.githubusercontent.com/tesseract-ocr/tessdata_best/refs/heads/main";
+const OCRS_MODELS_URL: &str = "https://ocrs-models.s3-accelerate.amazonaws.com";
+const DIGIT_MODEL_URL: &str =
+    "https://github.com/onnx/models/raw/main/validated/vision/classification/mnist/model";
+/// Environment variable that overrides where models are kept.
+pub const MODEL_DIR_ENV: &str = "QUIKSCORE_MODEL_DIR";
+/// Known SHA-256 hashes of model files. Any other model, including other tessdata languages, is
+/// only downloaded from its upstream URL and checked against the hash recorded next to it then.
+/// Mirrors and bundles can't provide it unless [`Options::allow_unpinned_models`] is set.
+const PINNED_MODEL_HASHES: &[(&str, [u8; 32])] = &[(
+    "eng.traineddata",
+    hex_literal::hex!("8280aed0782fe27257a68ea10fe7ef324ca0f8d85bd2fd145d1c2b560bcb66ba"),
+)];
 
-pub async fn get_or_download_models(
-    app: AppHandle,
-    frontend_channel: Channel<ModelDownload>,
-) -> Result<(), ModelDownloadError> {
-    let Options { ocr, mongo: _ } = AppState::get_options(&app);
-    if !ocr {
-        return Ok(());
+/// A model file in the cache directory and where it comes from.
+struct ModelFile {
+    name: String,
+    url: String,
+}
+
+impl ModelFile {
+    fn new(base_url: &str, name: String) -> Self {
+        Self {
+            url: format!("{base_url}/{name}"),
+            name,
+        }
     }
 
-    let mut cache_dir = dirs::cache_dir().ok_or(ModelDownloadError::CacheDirUnknown)?;
-    cache_dir.push("quikscore");
+    /// Where to download the file from, in order. Mirrors serve every file by name under their
+    /// base URL.
+    fn urls(&self, mirrors: &[String]) -> Vec<String> {
+        mirrors
+            .iter()
+            .map(|mirror| format!("{}/{}", mirror.trim_end_matches('/'), self.name))
+            .chain([self.url.clone()])
+            .collect()
+    }
+}
 
-    let tessdata = cache_dir.join("eng.traineddata");
+/// The files `backend` needs to read `languages`, plus the digit classifier.
+fn model_files(backend: OcrBackendKind, languages: &OcrLanguages) -> Vec<ModelFile> {
+    let mut files = match backend {
+        OcrBackendKind::Tesseract => languages
+            .required()
+            .into_iter()
+            .map(|language| ModelFile::new(TESSDATA_URL, format!("{language}.traineddata")))
+            .collect(),
+        OcrBackendKind::Ocrs => [DETECTION_MODEL, RECOGNITION_MODEL]
+            .into_iter()
+            .map(|name| ModelFile::new(OCRS_MODELS_URL, name.to_string()))
+            .collect(),
+    };
+    files.push(ModelFile::new(DIGIT_MODEL_URL, DIGIT_MODEL.to_string()));
+    files
+}
 
-    let tessdata_exists = tessdata.try_exists()?;
+/// Whether `name` is a model file that can be installed from or exported into a bundle.
+fn is_model_name(name: &str) -> bool {
+    name.ends_with(".traineddata")
+        || [DETECTION_MODEL, RECOGNITION_MODEL, DIGIT_MODEL].contains(&name)
+}
 
-    let mut hasher = Sha256::new();
-    let need_download_tessdata = if tessdata_exists {
-        let mut tessdata_file = File::open(&tessdata)?;
-        _ = std::io::copy(&mut tessdata_file, &mut hasher)?;
-        let hash = hasher.finalize_reset();
-        let hash_not_passed = hash[..] != ENG_TESSDATA_HASH[..];
-        if hash_not_passed {
-            warn!("Tesseract data hash mismatch, redownloading");
-        }
-        hash_not_passed
+fn pinned_hash(name: &str) -> Option<String> {
+    PINNED_MODEL_HASHES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, hash)| to_hex(hash))
+}
+
+/// Refuses models without a pinned hash, unless the user allowed them.
+fn check_pinned(name: &str, allow_unpinned: bool) -> Result<(), ModelDownloadError> {
+    if allow_unpinned || pinned_hash(name).is_some() {
+        Ok(())
     } else {
-        info!("Downloading Tesseract data...");
-        true
-    };
+        Err(ModelDownloadError::Unpinned(name.to_string()))
+    }
+}
 
-    if need_download_tessdata {
-        #[derive(Debug)]
-        enum Progress {
-            Progress { downloaded: u32, total: u32 },
-            Done,
-        }
-        let (tx, mut rx) =
-            tauri::async_runtime::channel::<Result<Progress, ModelDownloadError>>(1024);
-        let (mut progress, mut totals) = (0u32, 0u32);
-
-        let client = reqwest::Client::new();
-        let tx = tx.clone();
-        tauri::async_runtime::spawn(async move {
-            let resp_head = match client.head(ENG_TESSDATA).send().await {
-                Ok(it) => it,
-                Err(err) => {
-                    _ = tx.send(Err(err.into())).await;
-                    return;
-                }
-            };
-            let total = match resp_head
-                .headers()
-                .get(reqwest::header::CONTENT_LENGTH)
-                .ok_or(ModelDownloadError::NoContentLength)
-                .and_then(|h| Ok(h.to_str()?))
-                .and_then(|s| Ok(s.parse::<u32>()?))
-            {
-                Ok(it) => it,
-                Err(err) => {
-                    _ = tx.send(Err(err)).await;
-                    return;
-                }
-            };
+/// The mirrors `name` may be downloaded from. Unpinned models only come from upstream, unless the
+/// user allowed them.
+fn usable_mirrors<'a>(name: &str, mirrors: &'a [String], allow_unpinned: bool) -> &'a [String] {
+    if allow_unpinned || pinned_hash(name).is_some() {
+        mirrors
+    } else {
+        &[]
+    }
+}
 
-            let resp = match client.get(ENG_TESSDATA).send().await {
-                Ok(it) => it,
-                Err(err) => {
-                    _ = tx.send(Err(err.into())).await;
-                    return;
-                }
-            };
-            let mut file = match tokio::fs::File::create(tessdata).await {
-                Ok(it) => it,
-                Err(err) => {
-                    _ = tx.send(Err(err.into())).await;
-                    return;
-                }
-            };
-            let mut stream = resp.bytes_stream();
-
-            let mut downloaded = 0u32;
-
-            while let Some(chunk) = stream.next().await {
-                let chunk = match chunk {
-                    Ok(it) => it,
-                    Err(err) => {
-                        _ = tx.send(Err(err.into())).await;
-                        return;
-                    }
-                };
-                if let Err(err) = file.write_all(&chunk).await {
-                    _ = tx.send(Err(err.into())).await;
-                    return;
-                }
-                downloaded += chunk.len() as u32;
+/// Where the hash of an unpinned file is recorded on first download.
+fn recorded_hash_path(cache_dir: &Path, name: &str) -> PathBuf {
+    cache_dir.join(format!("{name}.sha256"))
+}
+
+fn file_hash(path: &Path) -> Result<String, ModelDownloadError> {
+    let mut hasher = Sha256::new();
+    _ = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
 
-                // send progress update (ignoring send errors if receiver closed)
-                let _ = tx.send(Ok(Progress::Progress { downloaded, total })).await;
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The hash the file called `name` should have, if one is known.
+fn expected_hash(cache_dir: &Path, name: &str) -> Result<Option<String>, ModelDownloadError> {
+    if let Some(hash) = pinned_hash(name) {
+        return Ok(Some(hash));
+    }
+    match std::fs::read_to_string(recorded_hash_path(cache_dir, name)) {
+        Ok(hash) => Ok(Some(hash.trim().to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn needs_download(cache_dir: &Path, name: &str) -> Result<bool, ModelDownloadError> {
+    let path = cache_dir.join(name);
+    if !path.try_exists()? {
+        info!("Downloading {name}...");
+        return Ok(true);
+    }
+    match expected_hash(cache_dir, name)? {
+        Some(expected) if file_hash(&path)? == expected => Ok(false),
+        Some(_) => {
+            warn!("Hash mismatch for {name}, redownloading");
+            Ok(true)
+        }
+        None => {
+            warn!("No hash recorded for {name}, redownloading");
+            Ok(true)
+        }
+    }
+}
+
+/// Downloads `model` from the first of `mirrors` that works, falling back to its upstream URL.
+async fn download_model(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    model: &ModelFile,
+    mirrors: &[String],
+    frontend_channel: &Channel<ModelDownload>,
+) -> Result<(), ModelDownloadError> {
+    let mut last_error = None;
+    for url in model.urls(mirrors) {
+        match download_from(client, cache_dir, model, &url, frontend_channel).await {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                warn!("Cannot download {} from {url}: {e}", model.name);
+                last_error = Some(e);
             }
-            let _ = tx.send(Ok(Progress::Done)).await;
-        });
+        }
+    }
+    Err(last_error.expect("the upstream URL is always tried"))
+}
 
-        while let Some(p) = rx.recv().await {
-            match p {
-                Ok(Progress::Progress { downloaded, total }) => {
-                    progress = downloaded;
-                    totals = total;
-                }
-                Ok(Progress::Done) => rx.close(),
-                Err(e) => {
-                    err_log!(&e);
-                    return Err(e);
+/// Where the ETag of an unfinished download is kept, so that resuming only appends bytes of the
+/// same file.
+fn etag_path(cache_dir: &Path, name: &str) -> PathBuf {
+    cache_dir.join(format!("{name}.part.etag"))
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), std::io::Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Where an unfinished download of `name` can be picked up, and the ETag it was started with.
+/// Only pinned models are resumed, since only a pinned hash can tell whether the joined file is
+/// right. Anything that can't be resumed is thrown away.
+fn resume_point(cache_dir: &Path, name: &str) -> Result<Option<(u64, String)>, ModelDownloadError> {
+    let part = cache_dir.join(format!("{name}.part"));
+    let etag_file = etag_path(cache_dir, name);
+    let etag = match std::fs::read_to_string(&etag_file) {
+        Ok(etag) => Some(etag),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let size = match std::fs::metadata(&part) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    match etag {
+        Some(etag) if size > 0 && pinned_hash(name).is_some() => Ok(Some((size, etag))),
+        _ => {
+            remove_if_exists(&part)?;
+            remove_if_exists(&etag_file)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Downloads `model` from `url` into a `.part` file, resuming where an earlier download of the
+/// same file left off, and moves it into place once its hash checks out. The hash is recorded for
+/// later checks if it isn't pinned, which is safe because unpinned downloads never resume.
+async fn download_from(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    model: &ModelFile,
+    url: &str,
+    frontend_channel: &Channel<ModelDownload>,
+) -> Result<(), ModelDownloadError> {
+    let path = cache_dir.join(&model.name);
+    let part = cache_dir.join(format!("{}.part", model.name));
+    let etag_file = etag_path(cache_dir, &model.name);
+
+    let resume = resume_point(cache_dir, &model.name)?;
+    let mut request = client.get(url);
+    if let Some((resume_from, etag)) = &resume {
+        info!("Resuming {} from byte {resume_from}", model.name);
+        // a server whose file changed since sends all of it instead
+        request = request
+            .header(reqwest::header::RANGE, format!("bytes={resume_from}-"))
+            .header(reqwest::header::IF_RANGE, etag);
+    }
+    let resp = request.send().await?;
+
+    // the server has nothing past what we have, so the part file should be complete; the pinned
+    // hash decides below
+    if resume.is_none() || resp.status() != StatusCode::RANGE_NOT_SATISFIABLE {
+        let resp = resp.error_for_status()?;
+        let (mut file, mut downloaded) = match resume {
+            Some((resume_from, _)) if resp.status() == StatusCode::PARTIAL_CONTENT => {
+                let file = tokio::fs::OpenOptions::new()
+                    .append(true)
+                    .open(&part)
+                    .await?;
+                (file, resume_from)
+            }
+            _ => {
+                match resp
+                    .headers()
+                    .get(reqwest::header::ETAG)
+                    .and_then(|etag| etag.to_str().ok())
+                {
+                    Some(etag) => std::fs::write(&etag_file, etag)?,
+                    None => remove_if_exists(&etag_file)?,
                 }
+                (tokio::fs::File::create(&part).await?, 0)
             }
+        };
+        let total = resp.content_length().map(|length| length + downloaded);
+
+        let mut stream = resp.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            downloaded += chunk.len() as u64;
             signal!(
                 frontend_channel,
                 ModelDownload::Progress {
-                    progress,
-                    total: totals
+                    model: model.name.clone(),
+                    progress: downloaded,
+                    total
                 }
+            );
+        }
+        file.flush().await?;
+    }
+
+    let actual = file_hash(&part)?;
+    let pinned = pinned_hash(&model.name);
+    if let Some(expected) = pinned.clone().filter(|expected| *expected != actual) {
+        remove_if_exists(&part)?;
+        remove_if_exists(&etag_file)?;
+        return Err(ModelDownloadError::HashMismatch {
+            model: model.name.clone(),
+            expected,
+            actual,
+        });
+    }
+    tokio::fs::rename(&part, &path).await?;
+    remove_if_exists(&etag_file)?;
+    if pinned.is_none() {
+        std::fs::write(recorded_hash_path(cache_dir, &model.name), &actual)?;
+    }
+    info!("Downloaded {}", model.name);
+    Ok(())
+}
+
+/// Where models are kept: `env_dir` (from [`MODEL_DIR_ENV`]) if set, then the `configured`
+/// directory, then the OS cache directory.
+fn resolve_model_dir(
+    env_dir: Option<OsString>,
+    configured: Option<&Path>,
+) -> Result<PathBuf, ModelDownloadError> {
+    let dir = match env_dir.filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match configured {
+            Some(dir) => dir.to_path_buf(),
+            None => dirs::cache_dir()
+                .ok_or(ModelDownloadError::CacheDirUnknown)?
+                .join("quikscore"),
+        },
+    };
+    if dir.is_relative() {
+        return Err(ModelDownloadError::RelativeModelDir(dir));
+    }
+    Ok(dir)
+}
+
+/// Creates and gives the directory models are downloaded and installed into, given the model
+/// directory chosen in the settings.
+pub fn model_dir(configured: Option<&Path>) -> Result<PathBuf, ModelDownloadError> {
+    let dir = resolve_model_dir(std::env::var_os(MODEL_DIR_ENV), configured)?;
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn model_dir_of<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+) -> Result<PathBuf, ModelDownloadError> {
+    model_dir(AppState::get_options(app).model_dir.as_deref())
+}
+
+pub async fn get_or_download_models(
+    app: AppHandle,
+    frontend_channel: Channel<ModelDownload>,
+) -> Result<(), ModelDownloadError> {
+    let Options {
+        ocr,
+        ocr_backend,
+        ocr_languages,
+        model_mirrors,
+        model_dir: configured_dir,
+        allow_unpinned_models,
+        ..
+    } = AppState::get_options(&app);
+    if !ocr {
+        return Ok(());
+    }
+    ocr_languages.validate()?;
+
+    let cache_dir = model_dir(configured_dir.as_deref())?;
+    let client = reqwest::Client::new();
+    let mut downloaded_any = false;
+    for model in model_files(ocr_backend, &ocr_languages) {
+        if needs_download(&cache_dir, &model.name)? {
+            download_model(
+                &client,
+                &cache_dir,
+                &model,
+                usable_mirrors(&model.name, &model_mirrors, allow_unpinned_models),
+                &frontend_channel,
             )
+            .await
+            .inspect_err(|e| err_log!(e))?;
+            downloaded_any = true;
         }
+    }
+    if downloaded_any {
         signal!(frontend_channel, ModelDownload::Success);
         info!("Download success!");
     }
 
-    _ = MODELS.set(cache_dir);
+    *MODELS.write().expect("poisoned") = Some(cache_dir);
     Ok(())
 }
 
@@ -156,6 +393,605 @@ pub async fn get_or_download_models(
     content = "data"
 )]
 pub enum ModelDownload {
-    Progress { progress: u32, total: u32 },
+    Progress {
+        /// File name of the model being downloaded.
+        model: String,
+        progress: u64,
+        /// Unknown when the server doesn't send the length.
+        total: Option<u64>,
+    },
     Success,
 }
+
+/// Copies the model called `name` out of `reader` into `cache_dir`, going through a `.part` file
+/// like downloads do. It has to match its pinned hash. Unpinned models are refused unless
+/// `allow_unpinned` is set, and then only have to match `bundled_hash` if they came with one.
+fn install_model(
+    cache_dir: &Path,
+    name: &str,
+    reader: &mut impl Read,
+    bundled_hash: Option<&str>,
+    allow_unpinned: bool,
+) -> Result<(), ModelDownloadError> {
+    check_pinned(name, allow_unpinned)?;
+    let part = cache_dir.join(format!("{name}.part"));
+    _ = std::io::copy(reader, &mut File::create(&part)?)?;
+
+    let actual = file_hash(&part)?;
+    let pinned = pinned_hash(name);
+    let expected = pinned.clone().or(bundled_hash.map(str::to_string));
+    if expected.is_none() {
+        warn!("No known hash for {name}, installing it as is");
+    }
+    if let Some(expected) = expected.filter(|expected| *expected != actual) {
+        std::fs::remove_file(&part)?;
+        return Err(ModelDownloadError::HashMismatch {
+            model: name.to_string(),
+            expected,
+            actual,
+        });
+    }
+    std::fs::rename(&part, cache_dir.join(name))?;
+    if pinned.is_none() {
+        std::fs::write(recorded_hash_path(cache_dir, name), &actual)?;
+    }
+    info!("Installed {name}");
+    Ok(())
+}
+
+/// Reads a hash written by [`export_model_bundle`].
+fn parse_hash(hash: &str) -> String {
+    hash.trim().to_ascii_lowercase()
+}
+
+/// Installs the model at `path`, checking it against the `.sha256` file next to it if there is
+/// one. Gives `None` for files that aren't models.
+fn install_model_file(
+    cache_dir: &Path,
+    path: &Path,
+    allow_unpinned: bool,
+) -> Result<Option<String>, ModelDownloadError> {
+    let Some(name) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| is_model_name(name))
+    else {
+        return Ok(None);
+    };
+    let bundled_hash = match std::fs::read_to_string(path.with_file_name(format!("{name}.sha256")))
+    {
+        Ok(hash) => Some(parse_hash(&hash)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    install_model(
+        cache_dir,
+        name,
+        &mut File::open(path)?,
+        bundled_hash.as_deref(),
+        allow_unpinned,
+    )?;
+    Ok(Some(name.to_string()))
+}
+
+/// Installs every model in a bundle made by [`export_model_bundle`]. Only the file names of the
+/// entries are used, so a bundle can't write outside of `cache_dir`.
+fn install_model_zip(
+    cache_dir: &Path,
+    path: &Path,
+    allow_unpinned: bool,
+) -> Result<Vec<String>, ModelDownloadError> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut hashes = HashMap::new();
+    let mut models = vec![];
+    for idx in 0..archive.len() {
+        let mut entry = archive.by_index(idx)?;
+        let Some(name) = entry
+            .enclosed_name()
+            .and_then(|path| Some(path.file_name()?.to_str()?.to_string()))
+            .filter(|_| !entry.is_dir())
+        else {
+            continue;
+        };
+        if let Some(model) = name.strip_suffix(".sha256") {
+            let mut hash = String::new();
+            _ = entry.read_to_string(&mut hash)?;
+            hashes.insert(model.to_string(), parse_hash(&hash));
+        } else if is_model_name(&name) {
+            check_pinned(&name, allow_unpinned)?;
+            models.push((idx, name));
+        }
+    }
+
+    for (idx, name) in &models {
+        let mut entry = archive.by_index(*idx)?;
+        install_model(
+            cache_dir,
+            name,
+            &mut entry,
+            hashes.get(name).map(String::as_str),
+            allow_unpinned,
+        )?;
+    }
+    Ok(models.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Installs models from a single model file, a directory of them or a `.zip` bundle into
+/// `cache_dir`, giving the names of the installed models. See [`install_model`] for which models
+/// are accepted.
+pub fn install_models_from(
+    cache_dir: &Path,
+    path: &Path,
+    allow_unpinned: bool,
+) -> Result<Vec<String>, ModelDownloadError> {
+    info!("Installing models from {}...", path.display());
+    let installed = if path.is_dir() {
+        let mut paths = std::fs::read_dir(path)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        paths.sort();
+        let mut installed = vec![];
+        for path in paths.iter().filter(|path| path.is_file()) {
+            installed.extend(install_model_file(cache_dir, path, allow_unpinned)?);
+        }
+        installed
+    } else if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+    {
+        install_model_zip(cache_dir, path, allow_unpinned)?
+    } else {
+        install_model_file(cache_dir, path, allow_unpinned)?
+            .into_iter()
+            .collect()
+    };
+    if installed.is_empty() {
+        return Err(ModelDownloadError::NoModels(path.display().to_string()));
+    }
+    Ok(installed)
+}
+
+/// Zips every model in `cache_dir` together with its hash, so that [`install_models_from`] can
+/// set up machines without internet. Models that no longer match their hash are left out.
+pub fn export_model_bundle(
+    cache_dir: &Path,
+    path: &Path,
+) -> Result<Vec<String>, ModelDownloadError> {
+    let mut names = std::fs::read_dir(cache_dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, std::io::Error>>()?;
+    names.retain(|name| is_model_name(name));
+    names.sort();
+
+    info!("Exporting models to {}...", path.display());
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+    let mut exported = vec![];
+    for name in names {
+        let model_path = cache_dir.join(&name);
+        let actual = file_hash(&model_path)?;
+        if expected_hash(cache_dir, &name)?.is_some_and(|expected| expected != actual) {
+            warn!("{name} does not match its hash, leaving it out");
+            continue;
+        }
+        zip.start_file(name.as_str(), options)?;
+        _ = std::io::copy(&mut File::open(&model_path)?, &mut zip)?;
+        zip.start_file(format!("{name}.sha256"), options)?;
+        zip.write_all(actual.as_bytes())?;
+        exported.push(name);
+    }
+    _ = zip.finish()?;
+    if exported.is_empty() {
+        std::fs::remove_file(path)?;
+        return Err(ModelDownloadError::NoModels(
+            cache_dir.display().to_string(),
+        ));
+    }
+    info!("Exported {} models", exported.len());
+    Ok(exported)
+}
+
+pub fn install_models_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    channel: Channel<ModelInstall>,
+) {
+    let Some(path) = path else {
+        signal!(channel, ModelInstall::Cancelled);
+        return;
+    };
+    let Options {
+        allow_unpinned_models,
+        ..
+    } = AppState::get_options(app);
+    let result = path
+        .into_path()
+        .map_err(ModelDownloadError::from)
+        .and_then(|path| install_models_from(&model_dir_of(app)?, &path, allow_unpinned_models));
+    match result {
+        Ok(installed) => signal!(channel, ModelInstall::Done { installed }),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                ModelInstall::Error {
+                    error: format!("Error whilst trying to install models: {e}")
+                }
+            )
+        }
+    }
+}
+
+pub fn export_model_bundle_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    let result = path
+        .into_path()
+        .map_err(ModelDownloadError::from)
+        .and_then(|path| export_model_bundle(&model_dir_of(app)?, &path));
+    match result {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "event",
+    content = "data"
+)]
+pub enum ModelInstall {
+    Cancelled,
+    Done {
+        /// File names of the installed models.
+        installed: Vec<String>,
+    },
+    Error {
+        error: String,
+    },
+}
+
+/// How a model file compares to the hash it should have.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HashStatus {
+    /// A hash is known, but the file hasn't been checked against it.
+    Unchecked,
+    Verified,
+    Mismatch,
+    /// No hash is pinned or recorded for the file.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledModel {
+    /// File name in the model directory.
+    pub name: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// Whether its hash is one of the pinned ones rather than recorded on first download.
+    pub pinned: bool,
+    pub hash: HashStatus,
+}
+
+/// Every model in `dir`, sorted by name. Their hashes are only worked out when `verify` is
+/// set, as that means reading each file.
+pub fn installed_models(
+    dir: &Path,
+    verify: bool,
+) -> Result<Vec<InstalledModel>, ModelDownloadError> {
+    let mut models = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_model_name(&name) || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let hash = match expected_hash(dir, &name)? {
+            None => HashStatus::Unknown,
+            Some(_) if !verify => HashStatus::Unchecked,
+            Some(expected) if file_hash(&entry.path())? == expected => HashStatus::Verified,
+            Some(_) => {
+                warn!("{name} does not match its hash");
+                HashStatus::Mismatch
+            }
+        };
+        models.push(InstalledModel {
+            pinned: pinned_hash(&name).is_some(),
+            size: entry.metadata()?.len(),
+            name,
+            hash,
+        });
+    }
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
+
+/// Deletes the models called `names` from `dir`, along with their recorded hashes and any
+/// unfinished downloads.
+pub fn delete_models(dir: &Path, names: &[String]) -> Result<(), ModelDownloadError> {
+    for name in names {
+        // only plain file names, so nothing outside of `dir` can be deleted
+        let plain_name = Path::new(name).file_name().and_then(|n| n.to_str()) == Some(name);
+        if !is_model_name(name) || !plain_name {
+            return Err(ModelDownloadError::UnknownModel(name.clone()));
+        }
+        for path in [
+            dir.join(name),
+            recorded_hash_path(dir, name),
+            dir.join(format!("{name}.part")),
+            etag_path(dir, name),
+        ] {
+            remove_if_exists(&path)?;
+        }
+        info!("Deleted {name}");
+    }
+    Ok(())
+}
+
+pub fn list_models<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    verify: bool,
+) -> Result<Vec<InstalledModel>, ModelDownloadError> {
+    installed_models(&model_dir_of(app)?, verify)
+}
+
+pub fn delete_models_of<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    names: &[String],
+) -> Result<(), ModelDownloadError> {
+    delete_models(&model_dir_of(app)?, names)
+}
+
+/// Checks that each of `mirrors` is a URL that files can be downloaded from.
+pub fn validate_mirrors(mirrors: &[String]) -> Result<(), ModelDownloadError> {
+    for mirror in mirrors {
+        let valid = reqwest::Url::parse(mirror)
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host());
+        if !valid {
+            return Err(ModelDownloadError::InvalidMirror(mirror.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::ocr::PATTERNS_FILE;
+
+    #[test]
+    fn test_mirror_urls() {
+        let model = ModelFile::new(TESSDATA_URL, "tha.traineddata".to_string());
+        let mirrors = [
+            "https://mirror.example.ac.th/models/".to_string(),
+            "http://10.0.0.5:8080".to_string(),
+        ];
+        assert_eq!(
+            model.urls(&mirrors),
+            [
+                "https://mirror.example.ac.th/models/tha.traineddata".to_string(),
+                "http://10.0.0.5:8080/tha.traineddata".to_string(),
+                format!("{TESSDATA_URL}/tha.traineddata"),
+            ]
+        );
+        assert!(validate_mirrors(&mirrors).is_ok());
+        assert!(validate_mirrors(&["file:///models".to_string()]).is_err());
+        assert!(validate_mirrors(&["mirror.example.ac.th".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_check_pinned() {
+        assert!(check_pinned("eng.traineddata", false).is_ok());
+        assert!(matches!(
+            check_pinned("tha.traineddata", false),
+            Err(ModelDownloadError::Unpinned(_))
+        ));
+        assert!(check_pinned("tha.traineddata", true).is_ok());
+    }
+
+    #[test]
+    fn test_default_models_download() {
+        let Options {
+            ocr_backend,
+            ocr_languages,
+            allow_unpinned_models,
+            ..
+        } = Options::default();
+        let mirrors = ["https://mirror.example.ac.th/models".to_string()];
+        let urls = |model: &ModelFile, allow_unpinned| {
+            model.urls(usable_mirrors(&model.name, &mirrors, allow_unpinned))
+        };
+        for backend in [ocr_backend, OcrBackendKind::Ocrs] {
+            for model in model_files(backend, &ocr_languages) {
+                let expected = if pinned_hash(&model.name).is_some() {
+                    2
+                } else {
+                    1
+                };
+                assert_eq!(urls(&model, allow_unpinned_models).len(), expected);
+                assert_eq!(urls(&model, allow_unpinned_models).last(), Some(&model.url));
+                assert_eq!(urls(&model, true).len(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_model_dir() {
+        let configured = std::env::temp_dir().join("quikscore_configured");
+        let from_env = std::env::temp_dir().join("quikscore_from_env");
+        assert_eq!(
+            resolve_model_dir(Some(from_env.clone().into()), Some(&configured)).unwrap(),
+            from_env
+        );
+        assert_eq!(
+            resolve_model_dir(Some("".into()), Some(&configured)).unwrap(),
+            configured
+        );
+        assert!(matches!(
+            resolve_model_dir(None, Some(Path::new("models"))),
+            Err(ModelDownloadError::RelativeModelDir(_))
+        ));
+        if let Some(cache_dir) = dirs::cache_dir() {
+            assert_eq!(
+                resolve_model_dir(None, None).unwrap(),
+                cache_dir.join("quikscore")
+            );
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("quikscore_{name}_{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_resume_point() {
+        let dir = temp_dir("models_resume");
+        let part = |name: &str| dir.join(format!("{name}.part"));
+
+        std::fs::write(part("eng.traineddata"), b"half").unwrap();
+        std::fs::write(etag_path(&dir, "eng.traineddata"), "\"abc\"").unwrap();
+        assert_eq!(
+            resume_point(&dir, "eng.traineddata").unwrap(),
+            Some((4, "\"abc\"".to_string()))
+        );
+
+        // without an ETag there's no telling whether the server still has the same file
+        std::fs::remove_file(etag_path(&dir, "eng.traineddata")).unwrap();
+        assert_eq!(resume_point(&dir, "eng.traineddata").unwrap(), None);
+        assert!(!part("eng.traineddata").exists());
+
+        // unpinned models always start over, so their recorded hash covers one whole download
+        std::fs::write(part("tha.traineddata"), b"half").unwrap();
+        std::fs::write(etag_path(&dir, "tha.traineddata"), "\"abc\"").unwrap();
+        assert_eq!(resume_point(&dir, "tha.traineddata").unwrap(), None);
+        assert!(!part("tha.traineddata").exists());
+        assert!(!etag_path(&dir, "tha.traineddata").exists());
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_model_bundle_round_trip() {
+        let from = temp_dir("models_from");
+        let to = temp_dir("models_to");
+        let bundle = from.join("bundle.zip");
+        std::fs::write(from.join(DIGIT_MODEL), b"digits").unwrap();
+        std::fs::write(from.join("notes.txt"), b"not a model").unwrap();
+
+        let exported = export_model_bundle(&from, &bundle).unwrap();
+        assert_eq!(exported, [DIGIT_MODEL]);
+        // its own hash is not enough for a model that isn't pinned
+        assert!(matches!(
+            install_models_from(&to, &bundle, false),
+            Err(ModelDownloadError::Unpinned(_))
+        ));
+        assert!(!to.join(DIGIT_MODEL).exists());
+        let installed = install_models_from(&to, &bundle, true).unwrap();
+        assert_eq!(installed, [DIGIT_MODEL]);
+        assert_eq!(std::fs::read(to.join(DIGIT_MODEL)).unwrap(), b"digits");
+        assert!(!needs_download(&to, DIGIT_MODEL).unwrap());
+        assert!(!to.join("notes.txt").exists());
+
+        // a bundle whose model doesn't match the hash it came with
+        std::fs::write(from.join(format!("{DIGIT_MODEL}.sha256")), to_hex(&[0; 32])).unwrap();
+        assert!(matches!(
+            install_models_from(&to, &from.join(DIGIT_MODEL), true),
+            Err(ModelDownloadError::HashMismatch { .. })
+        ));
+        assert!(!to.join(format!("{DIGIT_MODEL}.part")).exists());
+
+        // entries in folders are installed by file name, and ones that try to escape the cache
+        // directory are skipped
+        let mut zip = ZipWriter::new(File::create(&bundle).unwrap());
+        for name in ["models/tha.traineddata", "../chi_sim.traineddata"] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"model").unwrap();
+        }
+        zip.finish().unwrap();
+        assert_eq!(
+            install_models_from(&to, &bundle, true).unwrap(),
+            ["tha.traineddata"]
+        );
+        assert!(to.join("tha.traineddata").exists());
+        assert!(!to.with_file_name("chi_sim.traineddata").exists());
+
+        _ = std::fs::remove_dir_all(&from);
+        _ = std::fs::remove_dir_all(&to);
+    }
+
+    #[test]
+    fn test_manage_models() {
+        let dir = temp_dir("models_manage");
+        std::fs::write(dir.join("eng.traineddata"), b"not really english").unwrap();
+        std::fs::write(dir.join(DIGIT_MODEL), b"digits").unwrap();
+        std::fs::write(
+            recorded_hash_path(&dir, DIGIT_MODEL),
+            file_hash(&dir.join(DIGIT_MODEL)).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.join("tha.traineddata"), b"thai").unwrap();
+        std::fs::write(dir.join("tha.traineddata.part"), b"th").unwrap();
+        std::fs::write(dir.join(PATTERNS_FILE), b"\\d").unwrap();
+
+        let listed = installed_models(&dir, false).unwrap();
+        let summary = |models: &[InstalledModel]| {
+            models
+                .iter()
+                .map(|model| (model.name.clone(), model.hash))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(&listed),
+            [
+                ("eng.traineddata".to_string(), HashStatus::Unchecked),
+                (DIGIT_MODEL.to_string(), HashStatus::Unchecked),
+                ("tha.traineddata".to_string(), HashStatus::Unknown),
+            ]
+        );
+        assert!(listed[0].pinned);
+        assert_eq!(listed[1].size, 6);
+        assert_eq!(
+            summary(&installed_models(&dir, true).unwrap()),
+            [
+                ("eng.traineddata".to_string(), HashStatus::Mismatch),
+                (DIGIT_MODEL.to_string(), HashStatus::Verified),
+                ("tha.traineddata".to_string(), HashStatus::Unknown),
+            ]
+        );
+
+        delete_models(
+            &dir,
+            &["tha.traineddata".to_string(), DIGIT_MODEL.to_string()],
+        )
+        .unwrap();
+        assert!(!dir.join("tha.traineddata.part").exists());
+        assert!(!recorded_hash_path(&dir, DIGIT_MODEL).exists());
+        assert!(dir.join(PATTERNS_FILE).exists());
+        assert_eq!(installed_models(&dir, false).unwrap().len(), 1);
+        assert!(delete_models(&dir, &["../eng.traineddata".to_string()]).is_err());
+        assert!(delete_models(&dir, &[PATTERNS_FILE.to_string()]).is_err());
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -34,18 +34,34 @@ pub enum SheetError {
 
 #[derive(thiserror::Error, Debug)]
 pub enum ModelDownloadError {
-    #[error("Unsupported Operating System (cannot determine cache dir)")]
+    #[error("Unsupported Operating System (cannot determine cache dir), please choose a model directory or set QUIKSCORE_MODEL_DIR")]
     CacheDirUnknown,
+    #[error("Model directory must be an absolute path: {}", .0.display())]
+    RelativeModelDir(std::path::PathBuf),
     #[error("I/O error while trying to access models: {0}")]
     IOError(#[from] std::io::Error),
     #[error("Cannot make network request: {0}")]
     ReqwestError(#[from] reqwest::Error),
-    #[error("Cannot convert header to string: {0}")]
-    ToStrError(#[from] reqwest::header::ToStrError),
-    #[error("Cannot convert header string to number: {0}")]
-    ParseIntError(#[from] std::num::ParseIntError),
-    #[error("Response is missing content length")]
-    NoContentLength,
+    #[error("{model} has SHA-256 {actual}, expected {expected}")]
+    HashMismatch {
+        model: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Invalid mirror URL: {0}")]
+    InvalidMirror(String),
+    #[error("Invalid path: {0}")]
+    InvalidPath(#[from] tauri_plugin_fs::Error),
+    #[error("Cannot read or write model bundle: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("No model files found in {0}")]
+    NoModels(String),
+    #[error("{0} is not a model file")]
+    UnknownModel(String),
+    #[error("{0} has no pinned hash, allow unpinned models to use it anyway")]
+    Unpinned(String),
+    #[error("{0}")]
+    Ocr(#[from] OcrError),
 }
 
 impl serde::Serialize for ModelDownloadError {
@@ -83,11 +99,126 @@ pub enum ExportError {
     IncorrectState,
     #[error("Failed to serialize CSV: {0}")]
     Csv(#[from] csv::Error),
+    #[error("Failed to serialize JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to write XLSX workbook: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    #[error("Failed to write PDF: {0}")]
+    Pdf(#[from] printpdf::Error),
+    #[error("Failed to convert image: {0}")]
+    Image(#[from] opencv::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SyncError {
+    #[error("No database to sync to has been set up")]
+    Disabled,
+    #[error("Tried to sync scores while in an incorrect state. This is a bug.")]
+    IncorrectState,
+    #[error("Sheet details can only be synced to MongoDB")]
+    DetailsUnsupported,
+    #[error("Failed to convert score to BSON: {0}")]
+    Bson(#[from] mongodb::bson::ser::Error),
+    #[error("Failed to encode sheet image: {0}")]
+    Image(#[from] opencv::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Cannot find the app data directory: {0}")]
+    DataDir(#[from] tauri::Error),
+    #[error("Cannot read or write the sync queue: {0}")]
+    Queue(#[from] serde_json::Error),
     #[error("MongoDB error: {0}")]
     MongoDb(#[from] mongodb::error::Error),
+    #[error("PostgreSQL error: {0}")]
+    Postgres(#[from] tokio_postgres::Error),
+    #[error("Cannot set up TLS: {0}")]
+    Tls(#[from] native_tls::Error),
+}
+
+impl SyncError {
+    /// Whether the database itself failed, as opposed to the scores not being ready.
+    pub fn is_database_failure(&self) -> bool {
+        matches!(
+            self,
+            SyncError::MongoDb(_) | SyncError::Postgres(_) | SyncError::Tls(_)
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConnectionError {
+    #[error("No database has been set up")]
+    Disabled,
+    #[error("Invalid connection string: {0}")]
+    InvalidUri(String),
+    #[error("Cannot reach the database: {0}")]
+    Unreachable(String),
+    #[error("No permission to write to {target}: {error}")]
+    NoWritePermission { target: String, error: String },
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SettingsError {
+    #[error("Cannot find the app config directory: {0}")]
+    ConfigDir(#[from] tauri::Error),
+    #[error("Cannot read or write settings: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid settings file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Cannot access the credential store: {0}")]
+    Keyring(#[from] keyring::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReviewError {
+    #[error("Tried to resolve an ID review while in an incorrect state. This is a bug.")]
+    IncorrectState,
+    #[error("No sheet has student ID {0}")]
+    NoSuchSheet(String),
+    #[error(
+        "Student IDs have {} digits, got {0}",
+        crate::reconcile::STUDENT_ID_LEN
+    )]
+    InvalidId(String),
+    #[error("Student ID {0} is already used by another sheet")]
+    IdTaken(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StorageError {
+    #[error("Tried to save results while in an incorrect state. This is a bug.")]
+    IncorrectState,
+    #[error("Cannot find the app data directory: {0}")]
+    DataDir(#[from] tauri::Error),
+    #[error("Cannot create the app data directory: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("The results history task failed: {0}")]
+    Task(tauri::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RosterError {
+    #[error("Invalid path: {0}")]
+    InvalidPath(#[from] tauri_plugin_fs::Error),
+    #[error("Cannot open roster file: {0}")]
+    FileOperationFailed(#[from] std::io::Error),
+    #[error("Cannot read roster CSV: {0}")]
+    Csv(#[from] csv::Error),
 }
 
-/// Wrapper for Tesseract errors that happen at different stages.
+#[derive(thiserror::Error, Debug)]
+pub enum GradingError {
+    #[error("Grade {0} has a cut-off of {1}%, which is not between 0% and 100%")]
+    OutOfRange(String, f64),
+    #[error("Grading scale needs a grade starting at 0% so every score gets a grade")]
+    NoLowestBand,
+    #[error("Curve standard deviation cannot be negative")]
+    NegativeStdDev,
+}
+
+/// Wrapper for OCR errors that happen at different stages.
 #[derive(thiserror::Error, Debug)]
 pub enum OcrError {
     #[cfg(not(feature = "compile-tesseract"))]
@@ -105,8 +236,19 @@ pub enum OcrError {
     InvalidSize(#[from] std::num::TryFromIntError),
     #[error("Cannot encode image: {0}")]
     InvalidImage(#[from] opencv::Error),
+    #[error("Invalid OCR language: {0}")]
+    InvalidLanguage(String),
 
     #[cfg(feature = "compile-tesseract")]
     #[error("Tesseract: {0}")]
     Tesseract(#[from] tesseract::TesseractError),
+
+    #[error("ocrs: {0}")]
+    Ocrs(#[from] anyhow::Error),
+    #[error("Cannot load ocrs model: {0}")]
+    OcrsModel(#[from] rten::ModelLoadError),
+    #[error("Invalid image for ocrs: {0}")]
+    OcrsImage(#[from] ocrs::ImageSourceError),
+    #[error("Digit classifier: {0}")]
+    DigitModel(opencv::Error),
 }
@@ -0,0 +1,357 @@
+use crate::err_log;
+use crate::{
+    errors::ExportError,
+    scoring::{AnswerSheetResult, CheckedAnswer},
+    signal,
+    state::{AnswerSheet, AppState, CsvExport},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use tauri::{ipc::Channel, Emitter, Manager, Runtime};
+use tauri_plugin_fs::FilePath;
+
+/// Gradebook layouts that LMSes accept for importing grades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GradebookProfile {
+    /// Moodle's "Import grades from CSV": one identifier column that gets mapped to a user field
+    /// during import, followed by a column per grade item.
+    Moodle,
+    /// Google Classroom's grade import: email address, first name, last name, then assignments.
+    GoogleClassroom,
+    /// Canvas' gradebook CSV, with a "Points Possible" row under the header.
+    Canvas,
+}
+
+impl GradebookProfile {
+    /// Header of the column students are matched on, if the export doesn't override it.
+    pub fn default_identifier_column(&self) -> &'static str {
+        match self {
+            GradebookProfile::Moodle => "ID number",
+            GradebookProfile::GoogleClassroom => "Email Address",
+            GradebookProfile::Canvas => "SIS User ID",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GradebookExport {
+    pub profile: GradebookProfile,
+    /// Header of the column students are matched on. Uses the profile's default when `None`.
+    pub identifier_column: Option<String>,
+    /// What goes into the identifier column, where `{student_id}` and `{subject_id}` are
+    /// replaced with the sheet's details, e.g. `{student_id}@school.ac.th`.
+    pub identifier_pattern: String,
+    /// Name of the grade item/assignment the total is imported into.
+    pub assignment_name: String,
+    /// Also add a column with the points of every counted question.
+    pub per_question: bool,
+}
+
+fn identifier_for(pattern: &str, sheet: &AnswerSheet) -> String {
+    pattern
+        .replace("{student_id}", &sheet.student_id)
+        .replace("{subject_id}", &sheet.subject_id)
+}
+
+/// Splits a full name into first and last name at the first space.
+fn split_name(name: Option<&str>) -> (String, String) {
+    let name = name.unwrap_or_default().trim();
+    match name.split_once(char::is_whitespace) {
+        Some((first, last)) => (first.to_string(), last.trim().to_string()),
+        None => (name.to_string(), String::new()),
+    }
+}
+
+/// Builds every row of the gradebook, header included, sorted by student ID. `question_weights`
+/// are the points each question is worth, used for Canvas' "Points Possible" row.
+pub fn gradebook_rows(
+    export: &GradebookExport,
+    sheets: &[(&AnswerSheet, &AnswerSheetResult)],
+    max_score: u32,
+    question_weights: &[u8],
+) -> Vec<Vec<String>> {
+    let mut sheets = sheets.to_vec();
+    sheets.sort_by(|(a, _), (b, _)| a.student_id.cmp(&b.student_id));
+
+    // only questions the key has an answer for are worth a column
+    let questions = (0..36)
+        .filter(|q| {
+            export.per_question
+                && sheets
+                    .iter()
+                    .any(|(_, r)| r.graded_questions[*q].0.verdict() != CheckedAnswer::NotCounted)
+        })
+        .collect::<Vec<_>>();
+    let question_headers = questions
+        .iter()
+        .map(|q| format!("{} Q{}", export.assignment_name, q + 1));
+    let identifier_column = export
+        .identifier_column
+        .clone()
+        .unwrap_or_else(|| export.profile.default_identifier_column().to_string());
+
+    let leading_headers = match export.profile {
+        GradebookProfile::Moodle => vec![identifier_column.clone()],
+        GradebookProfile::GoogleClassroom => vec![
+            identifier_column.clone(),
+            "First Name".to_string(),
+            "Last Name".to_string(),
+        ],
+        GradebookProfile::Canvas => {
+            // Canvas matches on one of its own identifier columns, so keep them all in place
+            let mut headers = ["Student", "ID", "SIS User ID", "SIS Login ID", "Section"]
+                .map(String::from)
+                .to_vec();
+            if !headers.contains(&identifier_column) {
+                headers[2] = identifier_column.clone();
+            }
+            headers
+        }
+    };
+    let identifier_idx = leading_headers
+        .iter()
+        .position(|h| *h == identifier_column)
+        .expect("identifier column is always in the headers");
+
+    let mut rows = vec![leading_headers
+        .iter()
+        .cloned()
+        .chain([export.assignment_name.clone()])
+        .chain(question_headers)
+        .collect::<Vec<_>>()];
+
+    if export.profile == GradebookProfile::Canvas {
+        let mut points_possible = vec![String::new(); leading_headers.len()];
+        points_possible[0] = "    Points Possible".to_string();
+        rows.push(
+            points_possible
+                .into_iter()
+                .chain([max_score.to_string()])
+                .chain(questions.iter().map(|q| {
+                    question_weights
+                        .get(*q)
+                        .copied()
+                        .unwrap_or_default()
+                        .to_string()
+                }))
+                .collect(),
+        );
+    }
+
+    for (sheet, result) in sheets {
+        let identifier = identifier_for(&export.identifier_pattern, sheet);
+        let mut leading = match export.profile {
+            GradebookProfile::Moodle => vec![String::new()],
+            GradebookProfile::GoogleClassroom => {
+                let (first, last) = split_name(sheet.student_name.as_deref());
+                vec![String::new(), first, last]
+            }
+            GradebookProfile::Canvas => vec![
+                sheet.student_name.clone().unwrap_or_default(),
+                String::new(),
+                String::new(),
+                String::new(),
+                sheet.exam_room.clone().unwrap_or_default(),
+            ],
+        };
+        leading[identifier_idx] = identifier;
+
+        rows.push(
+            leading
+                .into_iter()
+                .chain([result.score.to_string()])
+                .chain(
+                    questions
+                        .iter()
+                        .map(|q| result.graded_questions[*q].1.to_string()),
+                )
+                .collect(),
+        );
+    }
+    rows
+}
+
+pub fn export_gradebook_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    export: GradebookExport,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    match export_gradebook_impl(app, path, &export) {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+pub fn export_gradebook_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+    export: &GradebookExport,
+) -> Result<(), ExportError> {
+    let path = path.into_path()?;
+    info!(
+        "Exporting {:?} gradebook to {}...",
+        export.profile,
+        path.display()
+    );
+
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let max_score = AppState::get_max_score(app).ok_or(ExportError::IncorrectState)?;
+    let key = AppState::get_key(app).ok_or(ExportError::IncorrectState)?;
+    let weights = AppState::get_weights(app).ok_or(ExportError::IncorrectState)?;
+    let question_weights = weights
+        .weights
+        .get(&key.subject_id)
+        .map(|(w, _)| w.as_slice())
+        .unwrap_or_default();
+    let sheets = results
+        .values()
+        .map(|(_, sheet, result)| (sheet, result))
+        .collect::<Vec<_>>();
+
+    let mut wtr = csv::Writer::from_writer(File::create(path)?);
+    for row in gradebook_rows(export, &sheets, max_score, question_weights) {
+        wtr.write_record(row)?;
+    }
+    wtr.flush()?;
+    info!(
+        "Finished exporting gradebook for {} students!",
+        sheets.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::array;
+
+    use super::*;
+    use crate::{scoring::CheckedQuestionGroup, state::unit_tests::SheetBuilder};
+
+    fn sheet(student_id: &str, name: &str) -> AnswerSheet {
+        SheetBuilder::default()
+            .student_id(student_id)
+            .student_name(name)
+            .exam_room("608")
+            .build()
+    }
+
+    /// Only the first 2 questions are counted, worth 2 points each.
+    fn result(right: usize) -> AnswerSheetResult {
+        let checked = |verdict| CheckedQuestionGroup {
+            A: verdict,
+            B: CheckedAnswer::NotCounted,
+            C: CheckedAnswer::NotCounted,
+            D: CheckedAnswer::NotCounted,
+            E: CheckedAnswer::NotCounted,
+        };
+        AnswerSheetResult {
+            correct: right as u32,
+            incorrect: 2 - right as u32,
+            score: right as u32 * 2,
+            graded_questions: array::from_fn(|q| match q {
+                q if q < right => (checked(CheckedAnswer::Correct), 2),
+                q if q < 2 => (checked(CheckedAnswer::Incorrect), 0),
+                _ => (checked(CheckedAnswer::NotCounted), 0),
+            }),
+        }
+    }
+
+    fn export(profile: GradebookProfile) -> GradebookExport {
+        GradebookExport {
+            profile,
+            identifier_column: None,
+            identifier_pattern: "{student_id}".to_string(),
+            assignment_name: "Midterm".to_string(),
+            per_question: false,
+        }
+    }
+
+    #[test]
+    fn test_moodle_rows() {
+        let (a, b) = (
+            sheet("65010009", "Gwen Stacy"),
+            sheet("65010003", "Marcia Cole"),
+        );
+        let (ra, rb) = (result(1), result(2));
+        let rows = gradebook_rows(
+            &GradebookExport {
+                per_question: true,
+                ..export(GradebookProfile::Moodle)
+            },
+            &[(&a, &ra), (&b, &rb)],
+            4,
+            &[2, 2],
+        );
+        assert_eq!(
+            rows[0],
+            ["ID number", "Midterm", "Midterm Q1", "Midterm Q2"]
+        );
+        assert_eq!(rows[1], ["65010003", "4", "2", "2"]);
+        assert_eq!(rows[2], ["65010009", "2", "2", "0"]);
+    }
+
+    #[test]
+    fn test_google_classroom_rows() {
+        let a = sheet("65010003", "Marcia  Cole");
+        let ra = result(2);
+        let rows = gradebook_rows(
+            &GradebookExport {
+                identifier_pattern: "{student_id}@school.ac.th".to_string(),
+                ..export(GradebookProfile::GoogleClassroom)
+            },
+            &[(&a, &ra)],
+            4,
+            &[2, 2],
+        );
+        assert_eq!(
+            rows[0],
+            ["Email Address", "First Name", "Last Name", "Midterm"]
+        );
+        assert_eq!(rows[1], ["65010003@school.ac.th", "Marcia", "Cole", "4"]);
+    }
+
+    #[test]
+    fn test_canvas_rows() {
+        let a = sheet("65010003", "Marcia Cole");
+        let ra = result(2);
+        let rows = gradebook_rows(
+            &GradebookExport {
+                identifier_column: Some("SIS Login ID".to_string()),
+                ..export(GradebookProfile::Canvas)
+            },
+            &[(&a, &ra)],
+            4,
+            &[2, 2],
+        );
+        assert_eq!(
+            rows[0],
+            [
+                "Student",
+                "ID",
+                "SIS User ID",
+                "SIS Login ID",
+                "Section",
+                "Midterm"
+            ]
+        );
+        assert_eq!(rows[1], ["    Points Possible", "", "", "", "", "4"]);
+        assert_eq!(rows[2], ["Marcia Cole", "", "", "65010003", "608", "4"]);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{errors::GradingError, statistics};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GradeBand {
+    pub grade: String,
+    /// Lowest percentage (0-100) that still gets this grade.
+    pub min_percent: f64,
+}
+
+/// Rescales percentages so the class has the given mean and standard deviation before looking up
+/// the grade, i.e. `target_mean + z * target_std_dev`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZScoreCurve {
+    pub target_mean: f64,
+    pub target_std_dev: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GradingScale {
+    pub bands: Vec<GradeBand>,
+    pub curve: Option<ZScoreCurve>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grade {
+    pub percentage: f64,
+    pub curved_percentage: Option<f64>,
+    pub grade: String,
+}
+
+impl Default for GradingScale {
+    /// The usual 8-level scale used in Thai schools.
+    fn default() -> Self {
+        Self::from_cutoffs(&[
+            ("4", 80.0),
+            ("3.5", 75.0),
+            ("3", 70.0),
+            ("2.5", 65.0),
+            ("2", 60.0),
+            ("1.5", 55.0),
+            ("1", 50.0),
+            ("0", 0.0),
+        ])
+    }
+}
+
+impl GradingScale {
+    pub fn from_cutoffs(cutoffs: &[(&str, f64)]) -> Self {
+        Self {
+            bands: cutoffs
+                .iter()
+                .map(|(grade, min_percent)| GradeBand {
+                    grade: grade.to_string(),
+                    min_percent: *min_percent,
+                })
+                .collect(),
+            curve: None,
+        }
+    }
+    pub fn pass_fail(pass_percent: f64) -> Self {
+        Self::from_cutoffs(&[("Pass", pass_percent), ("Fail", 0.0)])
+    }
+
+    /// Checks that every percentage is between 0 and 100, and that every score gets a grade.
+    pub fn validate(&self) -> Result<(), GradingError> {
+        if let Some(band) = self
+            .bands
+            .iter()
+            .find(|b| !(0.0..=100.0).contains(&b.min_percent))
+        {
+            return Err(GradingError::OutOfRange(
+                band.grade.clone(),
+                band.min_percent,
+            ));
+        }
+        if !self.bands.iter().any(|b| b.min_percent == 0.0) {
+            return Err(GradingError::NoLowestBand);
+        }
+        if let Some(ZScoreCurve { target_std_dev, .. }) = self.curve {
+            if target_std_dev < 0.0 {
+                return Err(GradingError::NegativeStdDev);
+            }
+        }
+        Ok(())
+    }
+
+    fn grade_for(&self, percentage: f64) -> String {
+        self.bands
+            .iter()
+            .filter(|b| percentage >= b.min_percent)
+            .max_by(|a, b| a.min_percent.total_cmp(&b.min_percent))
+            .map(|b| b.grade.clone())
+            .unwrap_or_default()
+    }
+
+    /// Grades every score at once, since curving depends on how the whole class did.
+    pub fn grade_all(&self, scores: &[u32], max_score: u32) -> Vec<Grade> {
+        let percentages = scores
+            .iter()
+            .map(|s| {
+                if max_score == 0 {
+                    0.0
+                } else {
+                    *s as f64 / max_score as f64 * 100.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let mean = statistics::mean(&percentages).unwrap_or_default();
+        let std_dev = statistics::std_dev(&percentages).unwrap_or_default();
+
+        percentages
+            .into_iter()
+            .map(|percentage| {
+                let curved_percentage = self.curve.as_ref().map(|curve| {
+                    let z = if std_dev == 0.0 {
+                        0.0
+                    } else {
+                        (percentage - mean) / std_dev
+                    };
+                    (curve.target_mean + z * curve.target_std_dev).clamp(0.0, 100.0)
+                });
+                Grade {
+                    grade: self.grade_for(curved_percentage.unwrap_or(percentage)),
+                    percentage,
+                    curved_percentage,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_default_scale() {
+        let grades = GradingScale::default().grade_all(&[40, 32, 29, 0], 40);
+        let grades = grades.iter().map(|g| g.grade.as_str()).collect::<Vec<_>>();
+        assert_eq!(grades, ["4", "4", "3", "0"]);
+    }
+
+    #[test]
+    fn test_pass_fail() {
+        let grades = GradingScale::pass_fail(50.0).grade_all(&[5, 4], 10);
+        assert_eq!(grades[0].grade, "Pass");
+        assert_eq!(grades[0].percentage, 50.0);
+        assert_eq!(grades[1].grade, "Fail");
+        assert_eq!(grades[1].curved_percentage, None);
+    }
+
+    #[test]
+    fn test_unordered_bands() {
+        let scale = GradingScale::from_cutoffs(&[("F", 0.0), ("A", 80.0), ("B", 70.0)]);
+        let grades = scale.grade_all(&[75], 100);
+        assert_eq!(grades[0].grade, "B");
+    }
+
+    #[test]
+    fn test_z_score_curve() {
+        let scale = GradingScale {
+            curve: Some(ZScoreCurve {
+                target_mean: 70.0,
+                target_std_dev: 10.0,
+            }),
+            ..GradingScale::from_cutoffs(&[("A", 80.0), ("B", 70.0), ("C", 60.0), ("F", 0.0)])
+        };
+        // 30%, 40% and 50% are 1.22 standard deviations apart
+        let grades = scale.grade_all(&[3, 4, 5], 10);
+
+        let curved = grades[0].curved_percentage.unwrap();
+        assert!(
+            (curved - (70.0 - 1.5f64.sqrt() * 10.0)).abs() < 1e-9,
+            "{curved}"
+        );
+        assert_eq!(grades[0].grade, "F");
+        assert_eq!(grades[1].curved_percentage, Some(70.0));
+        assert_eq!(grades[1].grade, "B");
+        assert_eq!(grades[2].grade, "A");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(GradingScale::default().validate().is_ok());
+        assert!(matches!(
+            GradingScale::from_cutoffs(&[("A", 80.0)]).validate(),
+            Err(GradingError::NoLowestBand)
+        ));
+        assert!(matches!(
+            GradingScale::from_cutoffs(&[("A", 120.0), ("F", 0.0)]).validate(),
+            Err(GradingError::OutOfRange(..))
+        ));
+    }
+}
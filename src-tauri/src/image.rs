@@ -1,13 +1,18 @@
+use crate::digits::EMPTY_BOX;
 use crate::err_log;
-use crate::ocr::{ImageSource, OcrEngine};
-use log::{debug, warn};
+use crate::ocr::{OcrBackend, OcrConfidence, OcrField, OcrText, MIN_CONFIDENCE};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
 use std::ops::RangeInclusive;
 use std::sync::{Arc, RwLock};
 use std::{array, mem};
 use tauri::ipc::Channel;
 
-use crate::errors::{SheetError, UploadError};
-use crate::scoring::{AnswerSheetResult, CheckedAnswer};
+use crate::errors::{ExportError, SheetError, UploadError};
+use crate::reconcile::{reconcile_student_id, DigitColumn};
+use crate::scoring::{AnswerSheetResult, BubbleRead, CheckedAnswer};
 use crate::{signal, state};
 use itertools::Itertools;
 use opencv::{
@@ -24,7 +29,7 @@ use tauri_plugin_dialog::FilePath;
 use tauri::{Emitter, Manager, Runtime};
 
 use crate::state::{
-    Answer, AnswerSheet, AnswerUpload, AppState, KeyUpload, Options, QuestionGroup,
+    Answer, AnswerSheet, AnswerUpload, AppState, CsvExport, KeyUpload, Options, QuestionGroup,
 };
 
 /// Creates a new **uninitialized!!!!** `Mat` with the same dimensions as the argument.
@@ -63,10 +68,18 @@ pub fn upload_key_image_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
         signal!(channel, KeyUpload::Cancelled);
         return;
     };
-    let Options { ocr, mongo: _ } = AppState::get_options(app);
+    let Options {
+        ocr,
+        ocr_backend,
+        ocr_languages,
+        ..
+    } = AppState::get_options(app);
     match handle_upload(
         file_path,
-        ocr.then(state::init_thread_ocr).flatten().as_ref(),
+        ocr.then(|| state::init_thread_ocr(ocr_backend, &ocr_languages))
+            .flatten()
+            .as_deref(),
+        None,
     ) {
         Ok((image, mat, key)) => AppState::upload_key(app, channel, image, mat, key.into()),
         Err(e) => {
@@ -100,7 +113,14 @@ pub fn upload_sheet_images_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
     };
 
     let images_count = paths.len();
-    let Options { ocr, mongo: _ } = AppState::get_options(app);
+    let Options {
+        ocr,
+        ocr_backend,
+        ocr_languages,
+        roster,
+        ..
+    } = AppState::get_options(app);
+    let roster_ids = roster.map(|r| r.into_iter().map(|e| e.student_id).collect::<Vec<_>>());
 
     let (tx, mut rx) = tauri::async_runtime::channel::<ProcessingState>(images_count);
     let stop_flag = Arc::new(RwLock::new(false));
@@ -115,14 +135,15 @@ pub fn upload_sheet_images_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
                 || {
                     (
                         tx.clone(),
-                        ocr.then(state::init_thread_ocr).flatten(),
+                        ocr.then(|| state::init_thread_ocr(ocr_backend, &ocr_languages))
+                            .flatten(),
                         Arc::clone(&stop_moved),
                     )
                 },
                 |(tx, ocr, stop), file_path| {
                     if !*stop.read().expect("not poisoned") {
                         _ = tx.try_send(ProcessingState::Starting);
-                        let res = handle_upload(file_path, ocr.as_ref());
+                        let res = handle_upload(file_path, ocr.as_deref(), roster_ids.as_deref());
                         _ = tx.try_send(ProcessingState::Finishing);
                         res
                     } else {
@@ -186,28 +207,166 @@ pub fn resize_relative_img(src: &Mat, relative: f64) -> opencv::Result<Mat> {
 
 fn handle_upload(
     path: FilePath,
-    ocr: Option<&OcrEngine>,
+    ocr: Option<&dyn OcrBackend>,
+    roster: Option<&[String]>,
 ) -> Result<(Vec<u8>, Mat, AnswerSheet), UploadError> {
     let mat = read_from_path(path)?;
     let mut splitted = prepare_answer_sheet(mat)?;
 
     let original = mem::take(&mut splitted.original);
     let bytes = mat_to_webp(&original).map_err(UploadError::from)?;
-    let answer_sheet = AnswerSheet::try_convert(splitted, ocr)?;
+    let answer_sheet = AnswerSheet::try_convert(splitted, ocr, roster)?;
     Ok((bytes, original, answer_sheet))
 }
 
 pub fn mat_to_webp(mat: &Mat) -> opencv::Result<Vec<u8>> {
+    encode_mat(mat, ImageFormat::Webp, 80)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Encodes `mat` in the given format. `quality` (0-100) applies to JPEG and WebP, while PNG is
+/// always lossless and only gets compressed harder as `quality` goes down.
+pub fn encode_mat(mat: &Mat, format: ImageFormat, quality: u8) -> opencv::Result<Vec<u8>> {
+    let quality = quality.min(100) as i32;
+    let params = match format {
+        ImageFormat::Png => vec![imgcodecs::IMWRITE_PNG_COMPRESSION, 9 - quality * 9 / 100],
+        ImageFormat::Jpeg => vec![imgcodecs::IMWRITE_JPEG_QUALITY, quality],
+        ImageFormat::Webp => vec![imgcodecs::IMWRITE_WEBP_QUALITY, quality],
+    };
     let mut buf: Vector<u8> = Vec::new().into();
     imgcodecs::imencode(
-        ".webp",
+        &format!(".{}", format.extension()),
         mat,
         &mut buf,
-        &vec![imgcodecs::IMWRITE_WEBP_QUALITY, 80].into(),
+        &params.into(),
     )?;
     Ok(buf.into())
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageExportOptions {
+    pub format: ImageFormat,
+    pub quality: u8,
+    /// File name of each image, where `{subject_id}`, `{student_id}`, `{exam_room}` and
+    /// `{exam_seat}` are replaced with the sheet's details, e.g. `{subject_id}_{student_id}`.
+    pub naming_pattern: String,
+}
+
+/// Fills in the naming pattern for `sheet`, replacing characters that are not allowed in file
+/// names and making sure the name ends with the format's extension.
+pub fn file_name_for(pattern: &str, sheet: &AnswerSheet, format: ImageFormat) -> String {
+    let name = pattern
+        .replace("{subject_id}", &sheet.subject_id)
+        .replace("{student_id}", &sheet.student_id)
+        .replace(
+            "{exam_room}",
+            sheet.exam_room.as_deref().unwrap_or_default(),
+        )
+        .replace(
+            "{exam_seat}",
+            sheet.exam_seat.as_deref().unwrap_or_default(),
+        )
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let name = name.trim();
+    let name = if name.is_empty() {
+        sheet.student_id.as_str()
+    } else {
+        name
+    };
+
+    let extension = format.extension();
+    let has_extension = Path::new(name)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension));
+    if has_extension {
+        name.to_string()
+    } else {
+        format!("{name}.{extension}")
+    }
+}
+
+pub fn export_images_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    options: ImageExportOptions,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    match export_images_impl(app, path, &options) {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+/// Writes every annotated sheet into the `path` folder. Sheets that end up with the same name get
+/// a `_2`, `_3`, ... suffix instead of overwriting each other.
+pub fn export_images_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+    options: &ImageExportOptions,
+) -> Result<(), ExportError> {
+    let folder = path.into_path()?;
+    info!("Exporting annotated images to {}...", folder.display());
+
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let mut used_names = HashSet::new();
+    for (mat, sheet, _) in results
+        .values()
+        .sorted_by(|(_, a, _), (_, b, _)| a.student_id.cmp(&b.student_id))
+    {
+        let name = file_name_for(&options.naming_pattern, sheet, options.format);
+        let name = (1..)
+            .map(|n| match n {
+                1 => name.clone(),
+                n => {
+                    let (stem, extension) = name.rsplit_once('.').unwrap_or((name.as_str(), ""));
+                    format!("{stem}_{n}.{extension}")
+                }
+            })
+            .find(|n| used_names.insert(n.clone()))
+            .expect("infinite iterator");
+        let bytes = encode_mat(mat, options.format, options.quality)?;
+        fs::write(folder.join(name), bytes)?;
+    }
+    info!("Finished exporting {} images!", results.len());
+
+    Ok(())
+}
+
 fn read_from_path(path: FilePath) -> Result<Mat, UploadError> {
     let path = path.into_path()?;
     let path_str = path.to_str().ok_or(UploadError::NonUtfPath)?;
@@ -331,7 +490,7 @@ fn roi_range_frac_ref(
     let rect = Rect_::from_point_size((0, 0).into(), mat.size()?);
     mat.roi(rect_range_frac(&rect, x, y))
 }
-fn roi_range_frac(
+pub(crate) fn roi_range_frac(
     mat: &impl MatTraitConst,
     x: RangeInclusive<f64>,
     y: RangeInclusive<f64>,
@@ -412,43 +571,88 @@ fn sorted_bubbles_by_filled<Src: Iterator<Item = Mat>>(
         .sorted_by(|a, b| PartialOrd::partial_cmp(&b.1, &a.1).expect("not NaN"))
 }
 
-fn extract_answers(answer_mats: Vec<Mat>) -> Result<[QuestionGroup; 36], SheetError> {
+type AnswersAndMultiMarks = ([QuestionGroup; 36], [[bool; 5]; 36]);
+fn extract_answers(answer_mats: Vec<Mat>) -> Result<AnswersAndMultiMarks, SheetError> {
     let mut out = answer_mats
         .into_iter()
         .map(|mat| {
-            let mut iter = (0..5).map(|row_idx| {
-                let row = roi_range_frac_ref(
-                    &mat,
-                    0.11946903..=1.0,
-                    (row_idx as f64 / 5.0)..=(row_idx as f64 + 1.0) / 5.0,
-                )?;
-                Result::<_, opencv::Error>::Ok(
-                    sorted_bubbles_by_filled((0..13u8).filter_map(move |bubble_idx| {
-                        roi_range_frac(
-                            &row,
-                            bubble_idx as f64 / 13.0..=(bubble_idx as f64 + 1.0) / 13.0,
-                            0.0..=1.0,
-                        )
-                        .inspect_err(|e| err_log!(e))
-                        .ok()
-                    }))
-                    .filter_map(|(idx, filled)| (filled > 0.4).then_some(idx as u8)),
-                )
+            let reads = (0..5)
+                .map(|row_idx| {
+                    let row = roi_range_frac_ref(
+                        &mat,
+                        0.11946903..=1.0,
+                        (row_idx as f64 / 5.0)..=(row_idx as f64 + 1.0) / 5.0,
+                    )?;
+                    Result::<_, opencv::Error>::Ok(Answer::read_bubbles_iter(
+                        sorted_bubbles_by_filled((0..13u8).filter_map(|bubble_idx| {
+                            roi_range_frac(
+                                &row,
+                                bubble_idx as f64 / 13.0..=(bubble_idx as f64 + 1.0) / 13.0,
+                                0.0..=1.0,
+                            )
+                            .inspect_err(|e| err_log!(e))
+                            .ok()
+                        }))
+                        .filter_map(|(idx, filled)| (filled > 0.4).then_some(idx as u8)),
+                    ))
+                })
+                .collect::<Result<Vec<_>, opencv::Error>>()?;
+            let multi_marked: [bool; 5] =
+                array::from_fn(|idx| reads[idx] == BubbleRead::MultipleMarks);
+            let mut answers = reads.into_iter().map(|read| match read {
+                BubbleRead::Answer(answer) => Some(answer),
+                BubbleRead::Blank | BubbleRead::MultipleMarks => None,
             });
-            Ok(QuestionGroup {
-                A: Answer::from_bubbles_iter(iter.next().expect("5 rows")?),
-                B: Answer::from_bubbles_iter(iter.next().expect("5 rows")?),
-                C: Answer::from_bubbles_iter(iter.next().expect("5 rows")?),
-                D: Answer::from_bubbles_iter(iter.next().expect("5 rows")?),
-                E: Answer::from_bubbles_iter(iter.next().expect("5 rows")?),
-            })
+            Ok((
+                QuestionGroup {
+                    A: answers.next().expect("5 rows"),
+                    B: answers.next().expect("5 rows"),
+                    C: answers.next().expect("5 rows"),
+                    D: answers.next().expect("5 rows"),
+                    E: answers.next().expect("5 rows"),
+                },
+                multi_marked,
+            ))
         })
         .collect::<Result<Vec<_>, opencv::Error>>()?
         .into_iter();
 
-    Ok(array::from_fn(|_| {
-        out.next().expect("should have exactly 36 groups")
-    }))
+    let mut multi_marked = [[false; 5]; 36];
+    let answers = array::from_fn(|idx| {
+        let (group, multi) = out.next().expect("should have exactly 36 groups");
+        multi_marked[idx] = multi;
+        group
+    });
+    Ok((answers, multi_marked))
+}
+
+/// Note: the mat passed into this function has to be just the bubble columns, nothing on top
+fn read_digit_columns<M: MatTraitConst + ToInputArray>(
+    mat: &M,
+    columns: u8,
+) -> Result<Vec<DigitColumn>, opencv::Error> {
+    (0..columns)
+        .map(|column_idx| {
+            let frac = column_idx as f64 / columns as f64;
+            let next_frac = (column_idx as f64 + 1.0) / columns as f64;
+            let column = roi_range_frac_ref(mat, frac..=next_frac, 0.0..=1.0)?;
+            let mut sorted = sorted_bubbles_by_filled((0..10).filter_map(|row_idx| {
+                let frac = row_idx as f64 / 10.0;
+                let next_frac = (row_idx as f64 + 1.0) / 10.0;
+                roi_range_frac(&column, 0.0..=1.0, frac..=next_frac)
+                    .inspect_err(|e| err_log!(e))
+                    .ok()
+                    .and_then(|mat| thresh(mat).inspect_err(|e| err_log!(e)).ok())
+            }));
+            let (digit, fill) = sorted.next().unwrap_or_default();
+            let runner_up_fill = sorted.next().map(|(_, f)| f).unwrap_or_default();
+            Ok(DigitColumn {
+                digit: digit as u8,
+                fill,
+                runner_up_fill,
+            })
+        })
+        .collect()
 }
 
 /// Note: the mat passed into this function has to be just the bubble columns, nothing on top
@@ -456,31 +660,19 @@ fn extract_digits_for_sub_stu<M: MatTraitConst + ToInputArray>(
     mat: &M,
     columns: u8,
 ) -> Result<String, opencv::Error> {
-    let mut digits = String::new();
-
-    for column_idx in 0..columns {
-        let frac = column_idx as f64 / columns as f64;
-        let next_frac = (column_idx as f64 + 1.0) / columns as f64;
-        let column = roi_range_frac_ref(mat, frac..=next_frac, 0.0..=1.0)?;
-        let circled = sorted_bubbles_by_filled((0..10).filter_map(|row_idx| {
-            let frac = row_idx as f64 / 10.0;
-            let next_frac = (row_idx as f64 + 1.0) / 10.0;
-            roi_range_frac(&column, 0.0..=1.0, frac..=next_frac)
-                .inspect_err(|e| err_log!(e))
-                .ok()
-                .and_then(|mat| thresh(mat).inspect_err(|e| err_log!(e)).ok())
-        }))
-        .find(|(_, filled)| *filled > 0.475)
-        .map(|(idx, _)| idx);
-        if let Some(circled) = circled {
-            digits.push_str(&circled.to_string());
-        }
-    }
-    Ok(digits)
+    Ok(read_digit_columns(mat, columns)?
+        .into_iter()
+        .filter(DigitColumn::is_filled)
+        .map(|c| c.digit.to_string())
+        .collect())
 }
 
 impl AnswerSheet {
-    fn try_convert(src: SplittedSheet, ocr: Option<&OcrEngine>) -> Result<Self, SheetError> {
+    fn try_convert(
+        src: SplittedSheet,
+        ocr: Option<&dyn OcrBackend>,
+        roster: Option<&[String]>,
+    ) -> Result<Self, SheetError> {
         let SplittedSheet {
             student_name: student_name_mat,
             subject_name: subject_name_mat,
@@ -496,33 +688,23 @@ impl AnswerSheet {
         let student_id_bubbles = roi_range_frac_ref(&student_id_mat, 0.0..=1.0, 0.12565445..=1.0)?;
 
         let subject_id = extract_digits_for_sub_stu(&subject_id_bubbles, 3)?;
-        let mut student_id = extract_digits_for_sub_stu(&student_id_bubbles, 9)?;
-        let answers = extract_answers(questions)?;
+        let student_id_columns = read_digit_columns(&student_id_bubbles, 9)?;
+        let (answers, multi_marked) = extract_answers(questions)?;
 
         let (mut student_name, mut subject_name, mut exam_room, mut exam_seat) =
             (None, None, None, None);
+        let mut written_student_id = None;
+        let mut ocr_confidence = None;
         if let Some(ocr) = ocr {
             let subject_id_written = roi_range_frac(&subject_id_mat, 0.0..=1.0, 0.0..=0.128205)?;
             let student_id_written =
                 roi_range_frac(&student_id_mat, 0.112..=1.0, 0.0..=0.12565445)?;
-            let (_written_subject_id, written_student_id) =
-                extract_subject_student_from_written_field(
-                    subject_id_written,
-                    student_id_written,
-                    ocr,
-                )?;
+            let (written_subject, written_student) = extract_subject_student_from_written_field(
+                subject_id_written,
+                student_id_written,
+                ocr,
+            )?;
 
-            if student_id.len() == 9 && student_id.starts_with('1') {
-                student_id.remove(0);
-            }
-            
-            if student_id != written_student_id {
-                //warn!("{} != {} && {} != {}", written_student_id, student_id, written_subject_id, subject_id);
-                if student_id.len() != 8 && written_student_id.len() == 8 {
-                    student_id = written_student_id;
-                }
-                warn!("User Fon and Enter differently");
-            }
             let (name, subject, room, seat) = extract_user_information(
                 student_name_mat,
                 subject_name_mat,
@@ -530,10 +712,48 @@ impl AnswerSheet {
                 exam_seat_mat,
                 ocr,
             )?;
-            _ = student_name.insert(name);
-            _ = subject_name.insert(subject);
-            _ = exam_room.insert(room);
-            _ = exam_seat.insert(seat);
+            let confidence = OcrConfidence {
+                student_name: name.confidence,
+                subject_name: subject.confidence,
+                exam_room: room.confidence,
+                exam_seat: seat.confidence,
+                subject_id: written_subject.confidence,
+                student_id: written_student.confidence,
+            };
+            let low_confidence = confidence.low_confidence_fields();
+            if !low_confidence.is_empty() {
+                warn!("Low OCR confidence for {low_confidence:?}: {confidence:?}");
+            }
+            // a shaky read, or one of unknown confidence, would only drag the bubbled ID towards
+            // the wrong student
+            if written_student
+                .confidence
+                .is_some_and(|c| c >= MIN_CONFIDENCE)
+            {
+                written_student_id = Some(written_student.text);
+            }
+            _ = ocr_confidence.insert(confidence);
+            _ = student_name.insert(name.text);
+            _ = subject_name.insert(subject.text);
+            _ = exam_room.insert(room.text);
+            _ = exam_seat.insert(seat.text);
+        }
+
+        let (student_id, id_review) = if ocr.is_some() || roster.is_some() {
+            reconcile_student_id(&student_id_columns, written_student_id.as_deref(), roster)
+        } else {
+            let bubbled = student_id_columns
+                .iter()
+                .filter(|c| c.is_filled())
+                .map(|c| c.digit.to_string())
+                .collect();
+            (bubbled, None)
+        };
+        if let Some(review) = &id_review {
+            warn!(
+                "Student ID {} needs review: {:?}",
+                review.chosen, review.reasons
+            );
         }
 
         Ok(Self {
@@ -544,6 +764,9 @@ impl AnswerSheet {
             exam_room,
             exam_seat,
             answers,
+            multi_marked,
+            id_review,
+            ocr_confidence,
         })
     }
 }
@@ -643,14 +866,14 @@ impl AnswerSheetResult {
     }
 }
 
-fn image_to_string(mat: &Mat, ocr: &OcrEngine) -> Result<String, SheetError> {
-    let bytes = mat.data_bytes()?;
-    let img_src = ImageSource::from_bytes(bytes, (mat.cols() as u32, mat.rows() as u32))?;
-    let ocr_input = ocr.prepare_input(img_src)?;
-    let text = ocr.get_text(ocr_input)?;
-    let rem_nl = text.lines().next().unwrap_or("").trim().to_string();
+fn image_to_string(
+    mat: &Mat,
+    ocr: &dyn OcrBackend,
+    field: OcrField,
+) -> Result<OcrText, SheetError> {
+    let text = ocr.get_text(mat, field)?;
 
-    Ok(rem_nl)
+    Ok(text.map(|t| t.lines().next().unwrap_or("").trim().to_string()))
 }
 
 fn extract_user_information(
@@ -658,61 +881,22 @@ fn extract_user_information(
     subject_name: Mat,
     exam_room: Mat,
     exam_seat: Mat,
-    ocr: &OcrEngine,
-) -> Result<(String, String, String, String), SheetError> {
+    ocr: &dyn OcrBackend,
+) -> Result<(OcrText, OcrText, OcrText, OcrText), SheetError> {
     // safe_imwrite("temp/debug_name.png", &name)?;
     // safe_imwrite("temp/debug_subject_name.png", &subject_name)?;
     // safe_imwrite("temp/debug_exam_room.png", &exam_room)?;
     // safe_imwrite("temp/debug_exam_seat.png", &exam_seat)?;
 
-    let name_string = image_to_string(&name, ocr)?;
-    let subject_string = image_to_string(&subject_name, ocr)?
-        .chars()
-        .filter(|c| c.is_alphabetic() || *c == ' ')
-        .collect::<String>();
-    let exam_room_string = image_to_string(&exam_room, ocr)?
-        .chars()
-        .filter_map(|c| match c {
-            c if c.is_ascii_digit() => Some(c),
-            'O' | 'o' => Some('0'),
-            'l' | 'I' | '|' => Some('1'),
-            'Z' => Some('2'),
-            'S' => Some('5'),
-            'G' => Some('6'),
-            'B' => Some('8'),
-            _ => None,
-        })
-        .collect::<String>();
-    let seat_string = {
-        let mut code: Option<char> = None;
-        let mut number: u8 = 0;
-        image_to_string(&exam_seat, ocr)?
-            .chars()
-            .filter(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
-            .enumerate()
-            .for_each(|(i, c)| {
-                if i == 0 {
-                    _ = code.get_or_insert(match c {
-                        '0' => 'O',
-                        c => c,
-                    });
-                } else {
-                    let num = match c {
-                        'O' => 0,
-                        'I' => 1,
-                        'Z' => 2,
-                        'S' => 5,
-                        'G' => 6,
-                        'B' => 8,
-                        c if c.is_ascii_digit() => c.to_digit(10).unwrap() as u8,
-                        _ => 0,
-                    };
-                    number = number * 10 + num;
-                }
-            });
-
-        format!("{}{:02}", code.unwrap_or_default(), number)
-    };
+    let name_string = image_to_string(&name, ocr, OcrField::StudentName)?;
+    // Thai tone marks are not alphabetic, so only strip what can't be part of a name
+    let subject_string = image_to_string(&subject_name, ocr, OcrField::SubjectName)?.map(|t| {
+        t.chars()
+            .filter(|c| !c.is_ascii_digit() && !c.is_ascii_punctuation() && !c.is_control())
+            .collect()
+    });
+    let exam_room_string = image_to_string(&exam_room, ocr, OcrField::ExamRoom)?.map(clean_text);
+    let seat_string = image_to_string(&exam_seat, ocr, OcrField::ExamSeat)?.map(parse_seat);
 
     Ok((name_string, subject_string, exam_room_string, seat_string))
 }
@@ -741,44 +925,130 @@ fn safe_imwrite<P: AsRef<Path>, M: MatTraitConst + ToInputArray>(
 fn extract_subject_student_from_written_field(
     subject_id_mat: Mat,
     student_id_mat: Mat,
-    ocr: &OcrEngine,
-) -> Result<(String, String), SheetError> {
+    ocr: &dyn OcrBackend,
+) -> Result<(OcrText, OcrText), SheetError> {
     // safe_imwrite("temp/debug_subject_r.png", &subject_id_mat)?;
     // safe_imwrite("temp/debug_student_r.png", &student_id_mat)?;
 
-    let rsub = image_to_string(&subject_id_mat, ocr)?;
-    let rstu = image_to_string(&student_id_mat, ocr)?;
+    let rsub = image_to_string(&subject_id_mat, ocr, OcrField::SubjectId)?;
+    let rstu = image_to_string(&student_id_mat, ocr, OcrField::StudentId)?;
 
-    let subject = clean_text(&rsub);
-    let student = clean_text(&rstu);
+    let subject = rsub.map(clean_text);
+    let student = rstu.map(clean_text);
 
     Ok((subject, student))
 }
 
+/// Keeps the digits of a numeric field, and the [`EMPTY_BOX`] marks of the digit classifier so the
+/// digits after an empty box keep their position. Tesseract is limited to digits for these, so
+/// anything else is stray whitespace.
 fn clean_text(raw: &str) -> String {
     raw.chars()
-        .filter_map(|c| {
-            if c == 'o' || c == 'O' {
-                Some('0')
-            } else if c.is_ascii_digit() {
-                Some(c)
-            } else {
-                None
-            }
-        })
+        .filter(|&c| c.is_ascii_digit() || c == EMPTY_BOX)
         .collect()
 }
 
+/// Formats a seat such as `A7` as `A07`: a row letter followed by a two digit number, as in the
+/// `\A\d\d` user pattern. The whitelist has to allow letters for the row, so letters that look
+/// like digits are read as digits in the number, and a `0` in place of the row is read as `O`.
+fn parse_seat(raw: &str) -> String {
+    let mut chars = raw
+        .chars()
+        .filter(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+    let code = chars
+        .next()
+        .map(|c| if c == '0' { 'O' } else { c })
+        .map(String::from)
+        .unwrap_or_default();
+    let number = chars
+        .filter_map(|c| match c {
+            'O' => Some(0),
+            'I' => Some(1),
+            'Z' => Some(2),
+            'S' => Some(5),
+            'G' => Some(6),
+            'B' => Some(8),
+            c => c.to_digit(10),
+        })
+        .take(2)
+        .fold(0u32, |number, digit| number * 10 + digit);
+    format!("{code}{number:02}")
+}
+
 #[cfg(test)]
 mod unit_tests {
     use std::path::PathBuf;
 
-    use crate::state;
+    use crate::{
+        ocr::{OcrBackendKind, OcrLanguages},
+        reconcile::ReviewReason,
+        state::{self, unit_tests::SheetBuilder},
+    };
 
     use super::*;
     use itertools::izip;
     use opencv::core;
 
+    #[test]
+    fn test_file_name_for() {
+        let sheet = SheetBuilder::default().exam_room("6/08").build();
+        assert_eq!(
+            file_name_for("{subject_id}_{student_id}", &sheet, ImageFormat::Png),
+            "10_65010003.png"
+        );
+        assert_eq!(
+            file_name_for("{exam_room}-{student_id}.JPG", &sheet, ImageFormat::Jpeg),
+            "6_08-65010003.JPG"
+        );
+        assert_eq!(
+            file_name_for("{exam_seat}", &sheet, ImageFormat::Webp),
+            "65010003.webp"
+        );
+    }
+
+    #[test]
+    fn test_parse_seat() {
+        assert_eq!(parse_seat("A7"), "A07");
+        assert_eq!(parse_seat(" B12\n"), "B12");
+        assert_eq!(parse_seat("C123"), "C12");
+        assert_eq!(parse_seat("A1B"), "A18");
+        assert_eq!(parse_seat("BO5"), "B05");
+        assert_eq!(parse_seat("0SI"), "O51");
+        assert_eq!(parse_seat(""), "00");
+        assert_eq!(clean_text(" 6501 0003\n"), "65010003");
+    }
+
+    #[test]
+    fn test_written_id_with_empty_box() {
+        let columns = |id: &str| {
+            id.chars()
+                .map(|c| DigitColumn {
+                    digit: c.to_digit(10).unwrap() as u8,
+                    fill: 0.9,
+                    runner_up_fill: 0.1,
+                })
+                .collect::<Vec<_>>()
+        };
+        // the fifth box was left empty, so the digits after it stay in place
+        let written = clean_text(" 6501 ?003\n");
+        assert_eq!(written, "6501?003");
+
+        let (id, review) = reconcile_student_id(&columns("65010003"), Some(&written), None);
+        assert_eq!(id, "65010003");
+        assert_eq!(review, None);
+
+        // a missed bubble is filled in from the roster, as the written ID backs it up
+        let roster = ["65010003".to_string(), "70000000".to_string()];
+        let (id, review) = reconcile_student_id(&columns("6501003"), Some(&written), Some(&roster));
+        assert_eq!(id, "65010003");
+        assert_eq!(review, None);
+
+        // but a written digit that disagrees still needs review
+        let (id, review) = reconcile_student_id(&columns("65010003"), Some("6501?008"), None);
+        assert_eq!(id, "65010003");
+        assert_eq!(review.unwrap().reasons, [ReviewReason::BubbleOcrMismatch]);
+    }
+
     fn test_key_image() -> FilePath {
         FilePath::Path(PathBuf::from("tests/assets/sample_valid_image.jpg"))
     }
@@ -799,7 +1069,7 @@ mod unit_tests {
     }
 
     fn setup_ocr_data() {
-        _ = state::MODELS.set(PathBuf::from("tests/assets"))
+        *state::MODELS.write().expect("poisoned") = Some(PathBuf::from("tests/assets"));
     }
 
     fn not_image() -> FilePath {
@@ -868,9 +1138,12 @@ mod unit_tests {
         let result = handle_upload(
             path,
             cfg!(feature = "ocr-tests")
-                .then(state::init_thread_ocr)
+                .then(|| {
+                    state::init_thread_ocr(OcrBackendKind::default(), &OcrLanguages::default())
+                })
                 .flatten()
-                .as_ref(),
+                .as_deref(),
+            None,
         );
         assert!(result.is_ok());
 
@@ -885,9 +1158,12 @@ mod unit_tests {
         let result = handle_upload(
             path,
             cfg!(feature = "ocr-tests")
-                .then(state::init_thread_ocr)
+                .then(|| {
+                    state::init_thread_ocr(OcrBackendKind::default(), &OcrLanguages::default())
+                })
                 .flatten()
-                .as_ref(),
+                .as_deref(),
+            None,
         );
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), UploadError::NotImage));
@@ -1012,7 +1288,7 @@ mod unit_tests {
             let mat = read_from_path(image).expect("Failed to read image");
             let SplittedSheet { questions, .. } =
                 prepare_answer_sheet(mat).expect("Fixing sheet failed");
-            let questions = extract_answers(questions).expect("reading questions failed");
+            let (questions, _) = extract_answers(questions).expect("reading questions failed");
             let res = questions
                 .into_iter()
                 .map(|group| {
@@ -1037,7 +1313,9 @@ mod unit_tests {
         #[test]
         fn check_extracted_ids_ocr() -> Result<(), SheetError> {
             setup_ocr_data();
-            let ocr = &state::init_thread_ocr().unwrap();
+            let ocr = state::init_thread_ocr(OcrBackendKind::default(), &OcrLanguages::default())
+                .unwrap();
+            let ocr = ocr.as_ref();
 
             for (i, path) in test_images().into_iter().take(3).enumerate() {
                 let mat = read_from_path(path).expect("Failed to read image");
@@ -1055,6 +1333,7 @@ mod unit_tests {
                     student_id_written,
                     ocr,
                 )?;
+                let (subject_id, student_id) = (subject_id.text, student_id.text);
 
                 if i == 0 {
                     assert_eq!(subject_id, "10", "Subject ID does not match expected value");
@@ -1082,7 +1361,9 @@ mod unit_tests {
         #[test]
         fn check_ocr_function() -> Result<(), SheetError> {
             setup_ocr_data();
-            let ocr = &state::init_thread_ocr().unwrap();
+            let ocr = state::init_thread_ocr(OcrBackendKind::default(), &OcrLanguages::default())
+                .unwrap();
+            let ocr = ocr.as_ref();
 
             for (i, path) in test_images().into_iter().take(3).enumerate() {
                 println!("image #{i}");
@@ -1102,6 +1383,8 @@ mod unit_tests {
                     exam_seat,
                     ocr,
                 )?;
+                let (name, subject, exam_room, seat) =
+                    (name.text, subject.text, exam_room.text, seat.text);
                 if i == 0 {
                     assert_eq!(name, "Elize Howells", "Name does not match expected value");
                     assert_eq!(
@@ -1,17 +1,26 @@
 #![feature(stmt_expr_attributes)]
 
-use crate::state::AppState;
+use crate::{err_log, state::AppState};
 use std::sync::Mutex;
 use tauri::Manager;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+mod analysis;
 mod commands;
+mod digits;
 mod download;
 mod errors;
+mod gradebook;
+mod grading;
 mod image;
 mod ocr;
+mod reconcile;
+mod report;
+mod roster;
 mod scoring;
+mod settings;
 mod state;
+mod statistics;
 mod storage;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -30,15 +39,61 @@ pub fn run() {
             commands::upload_sheet_images,
             commands::cancel_upload_sheets,
             commands::clear_sheet_images,
+            commands::import_roster,
             commands::set_ocr,
+            commands::set_ocr_backend,
+            commands::set_ocr_languages,
             commands::ensure_models,
+            commands::install_models,
+            commands::export_model_bundle,
+            commands::set_model_dir,
+            commands::list_models,
+            commands::verify_models,
+            commands::delete_models,
+            commands::set_model_mirrors,
+            commands::set_allow_unpinned_models,
             commands::export_csv,
+            commands::export_xlsx,
+            commands::export_json,
+            commands::export_pdf_reports,
+            commands::export_images,
+            commands::export_gradebook,
+            commands::item_analysis,
+            commands::export_item_analysis,
+            commands::distractor_analysis,
+            commands::export_distractor_analysis,
+            commands::exam_statistics,
+            commands::set_grading_scale,
             commands::enter_database_information,
+            commands::set_storage,
+            commands::test_database_connection,
+            commands::sync_database,
+            commands::retry_database_sync,
+            commands::queued_sync_rows,
+            commands::save_history,
+            commands::history_sessions,
+            commands::student_history,
             commands::login,
+            commands::id_reviews,
+            commands::resolve_id_review,
             commands::image_of,
         ])
         .setup(|app| {
             app.manage(Mutex::new(AppState::default()));
+            match settings::load_settings(app.handle()) {
+                Ok(settings) => {
+                    AppState::set_model_dir(app.handle(), settings.model_dir);
+                    match settings::load_storage(settings.storage) {
+                        Ok(storage) => AppState::set_storage(app.handle(), storage),
+                        Err(e) => err_log!(&e),
+                    }
+                }
+                Err(e) => err_log!(&e),
+            }
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                storage::retry_sync_queue_on_launch(&handle).await;
+            });
             Ok(())
         })
         .run(tauri::generate_context!())
@@ -1,6 +1,388 @@
-use crate::errors::OcrError;
-use opencv::{core::Size_, prelude::*};
-use std::path::PathBuf;
+use crate::{
+    digits::{DigitClassifier, WithDigitClassifier, DIGIT_MODEL},
+    err_log,
+    errors::OcrError,
+};
+use itertools::Itertools;
+use log::warn;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Reads with a mean word confidence below this are flagged for review.
+pub const MIN_CONFIDENCE: f32 = 0.6;
+
+/// A handwritten field of the sheet that is read with OCR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OcrField {
+    StudentName,
+    SubjectName,
+    ExamRoom,
+    ExamSeat,
+    SubjectId,
+    StudentId,
+}
+
+impl OcrField {
+    pub const ALL: [OcrField; 6] = [
+        OcrField::StudentName,
+        OcrField::SubjectName,
+        OcrField::ExamRoom,
+        OcrField::ExamSeat,
+        OcrField::SubjectId,
+        OcrField::StudentId,
+    ];
+}
+
+/// Text read from a field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrText {
+    pub text: String,
+    /// Mean word confidence, from 0 to 1, or `None` if the backend doesn't measure it.
+    pub confidence: Option<f32>,
+}
+
+impl OcrText {
+    pub fn map(self, f: impl FnOnce(&str) -> String) -> Self {
+        Self {
+            text: f(&self.text),
+            ..self
+        }
+    }
+}
+
+/// Mean word confidence of each field of a sheet, from 0 to 1, where it was measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrConfidence {
+    pub student_name: Option<f32>,
+    pub subject_name: Option<f32>,
+    pub exam_room: Option<f32>,
+    pub exam_seat: Option<f32>,
+    pub subject_id: Option<f32>,
+    pub student_id: Option<f32>,
+}
+
+impl OcrConfidence {
+    pub fn get(&self, field: OcrField) -> Option<f32> {
+        match field {
+            OcrField::StudentName => self.student_name,
+            OcrField::SubjectName => self.subject_name,
+            OcrField::ExamRoom => self.exam_room,
+            OcrField::ExamSeat => self.exam_seat,
+            OcrField::SubjectId => self.subject_id,
+            OcrField::StudentId => self.student_id,
+        }
+    }
+
+    /// Fields read with less than [`MIN_CONFIDENCE`], which should be checked by hand. Fields
+    /// without a measured confidence can't be judged, so they aren't listed.
+    pub fn low_confidence_fields(&self) -> Vec<OcrField> {
+        OcrField::ALL
+            .into_iter()
+            .filter(|field| self.get(*field).is_some_and(|c| c < MIN_CONFIDENCE))
+            .collect()
+    }
+}
+
+/// Name of the tesseract user patterns file in the model directory.
+pub const PATTERNS_FILE: &str = "tesseract.patterns";
+const DIGITS: &str = "0123456789";
+const UPPERCASE_AND_DIGITS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// How tesseract splits up a field before reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentationMode {
+    SingleLine,
+    SingleWord,
+}
+
+impl SegmentationMode {
+    /// Value of tesseract's `--psm` argument.
+    pub fn tesseract_arg(self) -> &'static str {
+        match self {
+            SegmentationMode::SingleLine => "single_line",
+            SegmentationMode::SingleWord => "single_word",
+        }
+    }
+}
+
+/// How tesseract should read an [`OcrField`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OcrFieldConfig {
+    pub segmentation: SegmentationMode,
+    /// Characters tesseract may output, or `None` for any character.
+    pub whitelist: Option<&'static str>,
+    /// Whether reads have to match the patterns in [`PATTERNS_FILE`]. The `tesseract` command
+    /// reads with them, but linked tesseract can't be given them, so there they only flag reads
+    /// that break them, see [`check_user_patterns`].
+    pub user_patterns: bool,
+}
+
+impl OcrField {
+    pub fn config(self) -> OcrFieldConfig {
+        match self {
+            OcrField::StudentName | OcrField::SubjectName => OcrFieldConfig {
+                segmentation: SegmentationMode::SingleLine,
+                whitelist: None,
+                user_patterns: false,
+            },
+            OcrField::ExamRoom => OcrFieldConfig {
+                segmentation: SegmentationMode::SingleWord,
+                whitelist: Some(DIGITS),
+                user_patterns: true,
+            },
+            OcrField::ExamSeat => OcrFieldConfig {
+                segmentation: SegmentationMode::SingleWord,
+                whitelist: Some(UPPERCASE_AND_DIGITS),
+                user_patterns: true,
+            },
+            OcrField::SubjectId | OcrField::StudentId => OcrFieldConfig {
+                segmentation: SegmentationMode::SingleLine,
+                whitelist: Some(DIGITS),
+                user_patterns: true,
+            },
+        }
+    }
+}
+
+/// Tesseract languages used for each kind of field, in tesseract's `-l` syntax, e.g. `tha+eng`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrLanguages {
+    pub student_name: String,
+    pub subject_name: String,
+    /// Used for the written IDs, exam room and seat, which are digits and a few Latin letters.
+    pub numbers: String,
+}
+
+impl Default for OcrLanguages {
+    fn default() -> Self {
+        Self {
+            student_name: "eng".to_string(),
+            subject_name: "eng".to_string(),
+            numbers: "eng".to_string(),
+        }
+    }
+}
+
+impl OcrLanguages {
+    pub fn for_field(&self, field: OcrField) -> &str {
+        match field {
+            OcrField::StudentName => &self.student_name,
+            OcrField::SubjectName => &self.subject_name,
+            OcrField::ExamRoom | OcrField::ExamSeat | OcrField::SubjectId | OcrField::StudentId => {
+                &self.numbers
+            }
+        }
+    }
+
+    /// The distinct `-l` arguments, each of which needs its own tesseract instance.
+    fn combinations(&self) -> Vec<&str> {
+        [&self.student_name, &self.subject_name, &self.numbers]
+            .into_iter()
+            .map(String::as_str)
+            .unique()
+            .collect()
+    }
+
+    /// Every language whose traineddata is needed, e.g. `["eng", "tha"]`.
+    pub fn required(&self) -> Vec<&str> {
+        self.combinations()
+            .into_iter()
+            .flat_map(|l| l.split('+'))
+            .unique()
+            .collect()
+    }
+
+    /// Language codes end up in file names and URLs, so only tesseract-style codes such as `eng`
+    /// or `chi_sim` are allowed.
+    pub fn validate(&self) -> Result<(), OcrError> {
+        for combination in self.combinations() {
+            for language in combination.split('+') {
+                let valid = !language.is_empty()
+                    && language
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+                if !valid {
+                    return Err(OcrError::InvalidLanguage(combination.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which OCR implementation reads the handwritten fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OcrBackendKind {
+    /// Tesseract, either linked in with `compile-tesseract` or the `tesseract` command.
+    #[default]
+    Tesseract,
+    /// [ocrs](https://github.com/robertknight/ocrs), which needs nothing installed but only reads
+    /// Latin text.
+    Ocrs,
+}
+
+impl OcrBackendKind {
+    /// Errors if the backend can't run on this machine.
+    pub fn check_available(self) -> Result<(), OcrError> {
+        #[cfg(not(feature = "compile-tesseract"))]
+        if self == OcrBackendKind::Tesseract && !TesseractEngine::check_tesseract()? {
+            return Err(OcrError::NoTesseract);
+        }
+        Ok(())
+    }
+
+    /// Creates the backend, with its models in `model_path`. The ID boxes are read with the
+    /// digit classifier when its model has been downloaded.
+    pub fn new_engine(
+        self,
+        model_path: &Path,
+        languages: &OcrLanguages,
+    ) -> Result<Box<dyn OcrBackend>, OcrError> {
+        let engine: Box<dyn OcrBackend> = match self {
+            OcrBackendKind::Tesseract => Box::new(TesseractEngine::new(
+                model_path.to_path_buf(),
+                languages.clone(),
+            )?),
+            OcrBackendKind::Ocrs => {
+                if *languages != OcrLanguages::default() {
+                    warn!("ocrs only reads Latin text, so the OCR languages are ignored");
+                }
+                Box::new(OcrsEngine::new(model_path)?)
+            }
+        };
+
+        let digit_model = model_path.join(DIGIT_MODEL);
+        if !digit_model.exists() {
+            return Ok(engine);
+        }
+        match DigitClassifier::load(&digit_model) {
+            Ok(digits) => Ok(Box::new(WithDigitClassifier {
+                inner: engine,
+                digits,
+            })),
+            Err(e) => {
+                err_log!(&e);
+                Ok(engine)
+            }
+        }
+    }
+}
+
+/// One character class of a tesseract user pattern such as `\A\d\d`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    Digit,
+    Letter,
+    Lowercase,
+    Uppercase,
+    Alphanumeric,
+    Punctuation,
+    Literal(char),
+}
+
+impl CharClass {
+    fn matches(self, c: char) -> bool {
+        match self {
+            CharClass::Digit => c.is_ascii_digit(),
+            CharClass::Letter => c.is_alphabetic(),
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Alphanumeric => c.is_alphanumeric(),
+            CharClass::Punctuation => c.is_ascii_punctuation(),
+            CharClass::Literal(literal) => c == literal,
+        }
+    }
+}
+
+/// A character class, and whether `\*` after it lets it repeat any number of times.
+type PatternItem = (CharClass, bool);
+
+fn parse_pattern(pattern: &str) -> Vec<PatternItem> {
+    let mut items: Vec<PatternItem> = vec![];
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        let class = match c {
+            '\\' => match chars.next() {
+                Some('d') => CharClass::Digit,
+                Some('c') => CharClass::Letter,
+                Some('a') => CharClass::Lowercase,
+                Some('A') => CharClass::Uppercase,
+                Some('n') => CharClass::Alphanumeric,
+                Some('p') => CharClass::Punctuation,
+                Some('*') => {
+                    if let Some((_, repeat)) = items.last_mut() {
+                        *repeat = true;
+                    }
+                    continue;
+                }
+                Some(c) => CharClass::Literal(c),
+                None => CharClass::Literal('\\'),
+            },
+            c => CharClass::Literal(c),
+        };
+        items.push((class, false));
+    }
+    items
+}
+
+fn matches_pattern(pattern: &[PatternItem], text: &[char]) -> bool {
+    let Some(((class, repeat), rest)) = pattern.split_first() else {
+        return text.is_empty();
+    };
+    let first_matches = text.first().is_some_and(|c| class.matches(*c));
+    if *repeat {
+        matches_pattern(rest, text) || (first_matches && matches_pattern(pattern, &text[1..]))
+    } else {
+        first_matches && matches_pattern(rest, &text[1..])
+    }
+}
+
+/// Whether `text`, ignoring whitespace, matches one of `patterns`. Anything goes without
+/// patterns.
+fn matches_user_patterns(text: &str, patterns: &[Vec<PatternItem>]) -> bool {
+    let text = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    patterns.is_empty() || patterns.iter().any(|p| matches_pattern(p, &text))
+}
+
+/// The patterns in [`PATTERNS_FILE`] in `dir`, one per line, or none if there is no such file.
+fn load_user_patterns(dir: &Path) -> Result<Vec<Vec<PatternItem>>, OcrError> {
+    match std::fs::read_to_string(dir.join(PATTERNS_FILE)) {
+        Ok(patterns) => Ok(patterns
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| parse_pattern(line.trim()))
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Zeroes the confidence of a read of `field` that breaks the user patterns, so it gets reviewed.
+/// Both tesseract backends check their reads this way, as only the command can be given the
+/// patterns to read with.
+fn check_user_patterns(read: OcrText, field: OcrField, patterns: &[Vec<PatternItem>]) -> OcrText {
+    if field.config().user_patterns && !matches_user_patterns(&read.text, patterns) {
+        OcrText {
+            confidence: Some(0.0),
+            ..read
+        }
+    } else {
+        read
+    }
+}
+
+/// Something that can read the text in a field of the sheet.
+pub trait OcrBackend {
+    fn get_text(&self, mat: &Mat, field: OcrField) -> Result<OcrText, OcrError>;
+}
 
 #[cfg(not(feature = "compile-tesseract"))]
 pub use tesseract_system::*;
@@ -8,6 +390,8 @@ pub use tesseract_system::*;
 #[cfg(feature = "compile-tesseract")]
 pub use tesseract_lib::*;
 
+pub use ocrs_backend::*;
+
 #[cfg(not(feature = "compile-tesseract"))]
 mod tesseract_system {
     use super::*;
@@ -16,14 +400,16 @@ mod tesseract_system {
         io::Write,
         process::{Command, Output, Stdio},
     };
-    /// An `ocrs::OcrEngine`-like wrapper for tesseract, provided by ~~opencv::text::OCRTesseract~~ THE
-    /// FUCKING TESSERACT COMMAND I'M TIRED OF LINKING ISSUES.
-    pub struct OcrEngine {
+    /// [`OcrBackend`] for tesseract, provided by ~~opencv::text::OCRTesseract~~ THE FUCKING
+    /// TESSERACT COMMAND I'M TIRED OF LINKING ISSUES.
+    pub struct TesseractEngine {
         tessdata_path: PathBuf,
+        languages: OcrLanguages,
+        /// Lines of [`PATTERNS_FILE`], empty if there is none.
+        patterns: Vec<Vec<PatternItem>>,
     }
-    pub struct ImageSource(Vec<u8>);
 
-    impl OcrEngine {
+    impl TesseractEngine {
         pub fn check_tesseract() -> Result<bool, OcrError> {
             let tess = Command::new("tesseract")
                 .arg("--version")
@@ -32,31 +418,53 @@ mod tesseract_system {
                 .status()?;
             Ok(tess.success())
         }
-        pub fn new(datapath: PathBuf) -> Result<OcrEngine, OcrError> {
-            if !OcrEngine::check_tesseract()? {
+        pub fn new(
+            datapath: PathBuf,
+            languages: OcrLanguages,
+        ) -> Result<TesseractEngine, OcrError> {
+            if !TesseractEngine::check_tesseract()? {
                 return Err(OcrError::NoTesseract);
             }
-            Ok(OcrEngine {
+            Ok(TesseractEngine {
+                patterns: load_user_patterns(&datapath)?,
                 tessdata_path: datapath,
+                languages,
             })
         }
-        /// Q: whats the point of this
-        /// A: mraow :3c
-        #[inline(always)]
-        pub fn prepare_input(&self, src: ImageSource) -> Result<ImageSource, OcrError> {
-            Ok(src)
-        }
-        pub fn get_text(&self, src: ImageSource) -> Result<String, OcrError> {
-            let mut tesseract = Command::new("tesseract")
+    }
+
+    impl OcrBackend for TesseractEngine {
+        fn get_text(&self, mat: &Mat, field: OcrField) -> Result<OcrText, OcrError> {
+            let png_bytes: Vec<u8> = {
+                let mut buf: Vector<u8> = vec![].into();
+                imencode_def(".png", mat, &mut buf)?;
+                buf.into()
+            };
+
+            let config = field.config();
+            let mut command = Command::new("tesseract");
+            _ = command
                 .arg("stdin")
                 .arg("stdout")
-                .args(["-l", "eng"])
+                .args(["-l", self.languages.for_field(field)])
                 .args(["--loglevel", "OFF"])
-                .args(["--psm", "single_line"])
+                .args(["--psm", config.segmentation.tesseract_arg()])
                 .args([
                     "--tessdata-dir",
                     self.tessdata_path.to_str().ok_or(OcrError::NoUnicode)?,
-                ])
+                ]);
+            if config.user_patterns {
+                let patterns = self.tessdata_path.join(PATTERNS_FILE);
+                _ = command.args([
+                    "--user-patterns",
+                    patterns.to_str().ok_or(OcrError::NoUnicode)?,
+                ]);
+            }
+            if let Some(whitelist) = config.whitelist {
+                _ = command.args(["-c", &format!("tessedit_char_whitelist={whitelist}")]);
+            }
+            _ = command.arg("tsv");
+            let mut tesseract = command
                 .stdin(Stdio::piped())
                 .stdout(Stdio::piped())
                 .spawn()?;
@@ -65,34 +473,53 @@ mod tesseract_system {
                 .stdin
                 .take()
                 .expect("no stdin????")
-                .write(&src.0)?;
+                .write(&png_bytes)?;
 
             let Output { stdout, .. } = tesseract.wait_with_output()?;
 
-            Ok(String::from_utf8_lossy(&stdout).to_string())
+            let read = parse_tsv(&String::from_utf8_lossy(&stdout));
+            Ok(check_user_patterns(read, field, &self.patterns))
         }
     }
 
-    impl ImageSource {
-        /// Q: why cant we just take the Mat and shove it in the imagesource directly
-        /// A: idk api compat or whatever
-        pub fn from_bytes(
-            bytes: &[u8],
-            (width, height): (u32, u32),
-        ) -> Result<ImageSource, OcrError> {
-            let mat = Mat::new_size_with_data(
-                Size_ {
-                    width: width.try_into()?,
-                    height: height.try_into()?,
-                },
-                bytes,
-            )?;
-            let png_bytes: Vec<u8> = {
-                let mut buf: Vector<u8> = vec![].into();
-                imencode_def(".png", &mat, &mut buf)?;
-                buf.into()
+    /// Turns tesseract's TSV output back into lines of text, along with the mean confidence of
+    /// the words in it.
+    pub(super) fn parse_tsv(tsv: &str) -> OcrText {
+        let mut text = String::new();
+        let mut confidences = vec![];
+        let mut last_line = None;
+        // level, page, block, paragraph, line, word, left, top, width, height, conf, text
+        for row in tsv.lines().skip(1) {
+            let columns = row.split('\t').collect::<Vec<_>>();
+            let [level, _, block, paragraph, line, _, _, _, _, _, confidence, word] = columns[..]
+            else {
+                continue;
+            };
+            let Ok(confidence) = confidence.parse::<f32>() else {
+                continue;
             };
-            Ok(ImageSource(png_bytes))
+            if level != "5" || word.trim().is_empty() {
+                continue;
+            }
+            if !text.is_empty() {
+                text.push(if last_line == Some((block, paragraph, line)) {
+                    ' '
+                } else {
+                    '\n'
+                });
+            }
+            last_line = Some((block, paragraph, line));
+            text.push_str(word);
+            confidences.push(confidence);
+        }
+        let confidence = if confidences.is_empty() {
+            0.0
+        } else {
+            confidences.iter().sum::<f32>() / confidences.len() as f32 / 100.0
+        };
+        OcrText {
+            text,
+            confidence: Some(confidence),
         }
     }
 }
@@ -100,45 +527,66 @@ mod tesseract_system {
 #[cfg(feature = "compile-tesseract")]
 mod tesseract_lib {
     use super::*;
-    use std::cell::RefCell;
+    use std::{cell::RefCell, collections::HashMap};
     use tesseract::{PageSegMode, Tesseract, TesseractError};
-    /// An `ocrs::OcrEngine`-like wrapper for tesseract, provided by ~~opencv::text::OCRTesseract~~ ~~THE
-    /// FUCKING TESSERACT COMMAND I'M TIRED OF LINKING ISSUES.~~ `tesseract::Tesseract` :yay:
-    pub struct OcrEngine {
-        tesseract: RefCell<Option<tesseract::Tesseract>>,
+    /// [`OcrBackend`] for tesseract, provided by ~~opencv::text::OCRTesseract~~ ~~THE FUCKING
+    /// TESSERACT COMMAND I'M TIRED OF LINKING ISSUES.~~ `tesseract::Tesseract` :yay:
+    pub struct TesseractEngine {
+        /// One instance per `-l` argument.
+        tesseracts: HashMap<String, RefCell<Option<tesseract::Tesseract>>>,
+        languages: OcrLanguages,
+        /// Lines of [`PATTERNS_FILE`], empty if there is none.
+        patterns: Vec<Vec<PatternItem>>,
     }
-    pub struct ImageSource(Mat);
 
-    impl OcrEngine {
-        pub fn check_tesseract() -> Result<bool, OcrError> {
-            Ok(true)
-        }
-        pub fn new(datapath: PathBuf) -> Result<OcrEngine, OcrError> {
-            let mut tesseract =
-                Tesseract::new(datapath.to_str(), Some("eng")).map_err(TesseractError::from)?;
-            tesseract.set_page_seg_mode(PageSegMode::PsmSingleLine);
-            Ok(OcrEngine {
-                tesseract: RefCell::new(Some(tesseract)),
+    impl TesseractEngine {
+        pub fn new(
+            datapath: PathBuf,
+            languages: OcrLanguages,
+        ) -> Result<TesseractEngine, OcrError> {
+            let tesseracts = languages
+                .combinations()
+                .into_iter()
+                .map(|language| {
+                    let tesseract = Tesseract::new(datapath.to_str(), Some(language))
+                        .map_err(TesseractError::from)?;
+                    Ok((language.to_string(), RefCell::new(Some(tesseract))))
+                })
+                .collect::<Result<_, OcrError>>()?;
+            let patterns = load_user_patterns(&datapath)?;
+            Ok(TesseractEngine {
+                tesseracts,
+                languages,
+                patterns,
             })
         }
-        /// Q: whats the point of this
-        /// A: mraow :3c
-        #[inline(always)]
-        pub fn prepare_input(&self, src: ImageSource) -> Result<ImageSource, OcrError> {
-            Ok(src)
-        }
-        pub fn get_text(&self, src: ImageSource) -> Result<String, OcrError> {
-            let mut self_mut = self.tesseract.borrow_mut();
+    }
+
+    impl OcrBackend for TesseractEngine {
+        fn get_text(&self, mat: &Mat, field: OcrField) -> Result<OcrText, OcrError> {
+            let mut self_mut = self.tesseracts[self.languages.for_field(field)].borrow_mut();
             let mut tess = self_mut.take().expect("should have tesseract instance");
 
-            let width = src.0.cols();
-            let height = src.0.rows();
-            let bytes_per_pixel = src.0.channels();
-            let bytes_per_line = src.0.step1_def()? as i32;
+            // User patterns are only loaded by tesseract's Init, and the tesseract crate has no
+            // way to pass variables to it. Setting `user_patterns_file` before Init doesn't help
+            // either, as Init starts over with a fresh instance, so the patterns only check reads.
+            let config = field.config();
+            tess.set_page_seg_mode(match config.segmentation {
+                SegmentationMode::SingleLine => PageSegMode::PsmSingleLine,
+                SegmentationMode::SingleWord => PageSegMode::PsmSingleWord,
+            });
+            tess = tess
+                .set_variable("tessedit_char_whitelist", config.whitelist.unwrap_or(""))
+                .map_err(TesseractError::from)?;
+
+            let width = mat.cols();
+            let height = mat.rows();
+            let bytes_per_pixel = mat.channels();
+            let bytes_per_line = mat.step1_def()? as i32;
 
             tess = tess
                 .set_frame(
-                    src.0.data_bytes()?,
+                    mat.data_bytes()?,
                     width,
                     height,
                     bytes_per_pixel,
@@ -146,29 +594,226 @@ mod tesseract_lib {
                 )
                 .map_err(TesseractError::from)?;
 
-            let result = tess.get_text().map_err(TesseractError::from)?;
+            let text = tess.get_text().map_err(TesseractError::from)?;
+            let confidence = tess.mean_text_conf().clamp(0, 100) as f32 / 100.0;
 
             _ = self_mut.insert(tess);
 
-            Ok(result)
+            let read = OcrText {
+                text,
+                confidence: Some(confidence),
+            };
+            Ok(check_user_patterns(read, field, &self.patterns))
+        }
+    }
+}
+
+mod ocrs_backend {
+    use super::*;
+    use crate::digits::EMPTY_BOX;
+    use ocrs::{ImageSource, OcrEngineParams};
+    use rten::Model;
+
+    pub const DETECTION_MODEL: &str = "text-detection.rten";
+    pub const RECOGNITION_MODEL: &str = "text-recognition.rten";
+
+    /// [`OcrBackend`] for ocrs, which runs in pure Rust.
+    pub struct OcrsEngine {
+        engine: ocrs::OcrEngine,
+    }
+
+    impl OcrsEngine {
+        pub fn new(model_path: &Path) -> Result<OcrsEngine, OcrError> {
+            let engine = ocrs::OcrEngine::new(OcrEngineParams {
+                detection_model: Some(Model::load_file(model_path.join(DETECTION_MODEL))?),
+                recognition_model: Some(Model::load_file(model_path.join(RECOGNITION_MODEL))?),
+                ..Default::default()
+            })?;
+            Ok(OcrsEngine { engine })
         }
     }
 
-    impl ImageSource {
-        /// Q: why cant we just take the Mat and shove it in the imagesource directly
-        /// A: idk api compat or whatever
-        pub fn from_bytes(
-            bytes: &[u8],
-            (width, height): (u32, u32),
-        ) -> Result<ImageSource, OcrError> {
-            let mat = Mat::new_size_with_data(
-                Size_ {
-                    width: width.try_into()?,
-                    height: height.try_into()?,
-                },
-                bytes,
+    /// Letters that are easily read in place of a digit, and that digit.
+    const DIGIT_LOOKALIKES: &[(char, char)] = &[
+        ('O', '0'),
+        ('o', '0'),
+        ('D', '0'),
+        ('Q', '0'),
+        ('I', '1'),
+        ('l', '1'),
+        ('|', '1'),
+        ('Z', '2'),
+        ('z', '2'),
+        ('S', '5'),
+        ('s', '5'),
+        ('G', '6'),
+        ('b', '6'),
+        ('B', '8'),
+        ('g', '9'),
+        ('q', '9'),
+    ];
+
+    /// Fits `text` into `whitelist` one character for one, since dropping a character would shift
+    /// the rest of an ID. Lowercase letters become uppercase and lookalike letters digits where
+    /// the whitelist needs them, and anything else becomes an [`EMPTY_BOX`], an unread character.
+    pub(super) fn fit_whitelist(text: &str, whitelist: Option<&str>) -> String {
+        let Some(whitelist) = whitelist else {
+            return text.to_string();
+        };
+        text.chars()
+            .map(|c| {
+                let upper = c.to_ascii_uppercase();
+                if c.is_whitespace() || whitelist.contains(c) {
+                    c
+                } else if whitelist.contains(upper) {
+                    upper
+                } else {
+                    DIGIT_LOOKALIKES
+                        .iter()
+                        .find(|(lookalike, _)| *lookalike == c)
+                        .map(|(_, digit)| *digit)
+                        .filter(|digit| whitelist.contains(*digit))
+                        .unwrap_or(EMPTY_BOX)
+                }
+            })
+            .collect()
+    }
+
+    impl OcrBackend for OcrsEngine {
+        /// ocrs takes the allowed characters when the engine is created, and loading its models
+        /// for each whitelist is slow, so one engine reads everything and the read is fitted into
+        /// the whitelist afterwards, see [`fit_whitelist`]. ocrs has no notion of confidence, so its reads are never flagged, but
+        /// never trusted over a measured read either.
+        fn get_text(&self, mat: &Mat, field: OcrField) -> Result<OcrText, OcrError> {
+            let source = ImageSource::from_bytes(
+                mat.data_bytes()?,
+                (mat.cols().try_into()?, mat.rows().try_into()?),
             )?;
-            Ok(ImageSource(mat.clone_pointee()))
+            let input = self.engine.prepare_input(source)?;
+            Ok(OcrText {
+                text: fit_whitelist(&self.engine.get_text(&input)?, field.config().whitelist),
+                confidence: None,
+            })
         }
     }
 }
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_required_languages() {
+        let languages = OcrLanguages {
+            student_name: "tha+eng".to_string(),
+            subject_name: "eng+tha".to_string(),
+            numbers: "eng".to_string(),
+        };
+        assert_eq!(languages.required(), ["tha", "eng"]);
+        assert_eq!(languages.for_field(OcrField::StudentId), "eng");
+        assert!(languages.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_language() {
+        let languages = OcrLanguages {
+            student_name: "../eng".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            languages.validate(),
+            Err(OcrError::InvalidLanguage(l)) if l == "../eng"
+        ));
+        let languages = OcrLanguages {
+            numbers: "eng+".to_string(),
+            ..Default::default()
+        };
+        assert!(languages.validate().is_err());
+    }
+
+    #[test]
+    fn test_low_confidence_fields() {
+        let confidence = OcrConfidence {
+            student_name: Some(0.9),
+            subject_name: None,
+            exam_room: Some(0.3),
+            exam_seat: Some(0.95),
+            subject_id: Some(0.7),
+            student_id: Some(0.59),
+        };
+        assert_eq!(
+            confidence.low_confidence_fields(),
+            [OcrField::ExamRoom, OcrField::StudentId]
+        );
+    }
+
+    #[test]
+    fn test_user_patterns() {
+        let patterns = ["\\d\\d\\d", "\\A\\d\\d", "\\d\\*-\\d"].map(parse_pattern);
+        let matches = |text| matches_user_patterns(text, &patterns);
+        assert!(matches("608"));
+        assert!(matches(" A07\n"));
+        assert!(matches("-1"));
+        assert!(matches("6501-2"));
+        assert!(!matches("6O8"));
+        assert!(!matches("A7"));
+        assert!(!matches("a07"));
+        assert!(matches_user_patterns("anything", &[]));
+    }
+
+    #[test]
+    fn test_check_user_patterns() {
+        let patterns = load_user_patterns(Path::new("tests/assets")).unwrap();
+        assert_eq!(patterns.len(), 3);
+        let read = |text: &str| OcrText {
+            text: text.to_string(),
+            confidence: Some(0.9),
+        };
+        let checked = |text, field| check_user_patterns(read(text), field, &patterns);
+        assert_eq!(checked("A07", OcrField::ExamSeat).confidence, Some(0.9));
+        // both tesseract backends keep the read but flag it
+        let broken = checked("A7", OcrField::ExamSeat);
+        assert_eq!((broken.text.as_str(), broken.confidence), ("A7", Some(0.0)));
+        // names have no patterns
+        assert_eq!(
+            checked("Somchai", OcrField::StudentName).confidence,
+            Some(0.9)
+        );
+        assert!(load_user_patterns(Path::new("tests")).unwrap().is_empty());
+    }
+
+    #[cfg(not(feature = "compile-tesseract"))]
+    #[test]
+    fn test_parse_tsv() {
+        let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t300\t40\t-1\t
+4\t1\t1\t1\t1\t0\t2\t3\t280\t30\t-1\t
+5\t1\t1\t1\t1\t1\t2\t3\t120\t30\t90\tSomchai
+5\t1\t1\t1\t1\t2\t130\t3\t150\t30\t70\tJaidee
+5\t1\t1\t1\t2\t1\t2\t35\t50\t30\t50\tM.6
+";
+        let OcrText { text, confidence } = tesseract_system::parse_tsv(tsv);
+        assert_eq!(text, "Somchai Jaidee\nM.6");
+        assert!((confidence.unwrap() - 0.7).abs() < 1e-6);
+        assert_eq!(tesseract_system::parse_tsv("").confidence, Some(0.0));
+    }
+
+    #[test]
+    fn test_fit_whitelist() {
+        assert_eq!(
+            ocrs_backend::fit_whitelist("65O1 0003\n", Some(DIGITS)),
+            "6501 0003\n"
+        );
+        assert_eq!(ocrs_backend::fit_whitelist("6O8", Some(DIGITS)), "608");
+        // nothing is dropped, so the digits after an unreadable one keep their place
+        assert_eq!(
+            ocrs_backend::fit_whitelist("65#10003", Some(DIGITS)),
+            "65?10003"
+        );
+        assert_eq!(
+            ocrs_backend::fit_whitelist("a0S", Some(UPPERCASE_AND_DIGITS)),
+            "A0S"
+        );
+        assert_eq!(ocrs_backend::fit_whitelist("A07", None), "A07");
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{digits::EMPTY_BOX, roster::edit_distance};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Number of digits in a student ID.
+pub const STUDENT_ID_LEN: usize = 8;
+/// A column counts as filled in above this fill ratio.
+pub const FILLED_THRESHOLD: f32 = 0.475;
+/// Roster IDs at most this many edits away from the bubbled or written ID become candidates.
+const MAX_CANDIDATE_DISTANCE: usize = 2;
+/// The best candidate has to beat the runner-up by this much to be accepted without review.
+const MIN_MARGIN: f32 = 0.15;
+
+const BUBBLE_WEIGHT: f32 = 0.45;
+const OCR_WEIGHT: f32 = 0.35;
+const ROSTER_WEIGHT: f32 = 0.2;
+
+/// Fill ratios of the two most filled bubbles in one column of the ID field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DigitColumn {
+    pub digit: u8,
+    pub fill: f32,
+    pub runner_up_fill: f32,
+}
+
+impl DigitColumn {
+    pub fn is_filled(&self) -> bool {
+        self.fill > FILLED_THRESHOLD
+    }
+
+    /// How clearly the bubbled digit stands out from the rest of the column, from 0 to 1.
+    pub fn confidence(&self) -> f32 {
+        if !self.is_filled() {
+            return 0.0;
+        }
+        ((self.fill - self.runner_up_fill) / self.fill).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReviewReason {
+    /// The bubbled and written IDs disagree and neither clearly wins.
+    BubbleOcrMismatch,
+    /// The chosen ID is not on the roster.
+    NotOnRoster,
+    /// No candidate has the right number of digits.
+    InvalidLength,
+    /// A roster ID near the bubbled one scored higher, but the written ID doesn't back it up, so
+    /// the bubbled ID was kept.
+    NearRosterId,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdCandidate {
+    pub student_id: String,
+    pub score: f32,
+}
+
+/// A student ID that could not be read with confidence and should be checked by hand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdReview {
+    pub reasons: Vec<ReviewReason>,
+    pub bubbled: String,
+    pub written: Option<String>,
+    pub chosen: String,
+    /// Best candidates first.
+    pub candidates: Vec<IdCandidate>,
+}
+
+/// Digits that were filled in along with their confidence. A leading `1` on a full 9 column read
+/// is dropped, since that column is the prefix of the year code.
+fn bubbled_digits(columns: &[DigitColumn]) -> Vec<(char, f32)> {
+    let mut digits = columns
+        .iter()
+        .filter(|c| c.is_filled())
+        .map(|c| ((b'0' + c.digit) as char, c.confidence()))
+        .collect::<Vec<_>>();
+    if digits.len() == STUDENT_ID_LEN + 1 && digits[0].0 == '1' {
+        digits.remove(0);
+    }
+    digits
+}
+
+fn similarity(a: &str, b: &str) -> f32 {
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 0.0;
+    }
+    1.0 - edit_distance(a, b) as f32 / len as f32
+}
+
+/// Edits between the written ID and `id`. An [`EMPTY_BOX`] in `written` stands for a digit that
+/// wasn't read, so it matches any digit in the same position.
+fn written_distance(written: &str, id: &str) -> usize {
+    if written.contains(EMPTY_BOX) && written.len() == id.len() {
+        written
+            .chars()
+            .zip(id.chars())
+            .filter(|&(w, c)| w != EMPTY_BOX && w != c)
+            .count()
+    } else {
+        edit_distance(written, id)
+    }
+}
+
+/// [`similarity`] to the written ID, see [`written_distance`].
+fn written_similarity(candidate: &str, written: &str) -> f32 {
+    let len = candidate.len().max(written.len());
+    if len == 0 {
+        return 0.0;
+    }
+    1.0 - written_distance(written, candidate) as f32 / len as f32
+}
+
+/// Agreement with the bubbles, weighing each matching digit by how clearly it was bubbled.
+fn bubble_score(candidate: &str, bubbled: &[(char, f32)]) -> f32 {
+    if bubbled.is_empty() {
+        return 0.0;
+    }
+    if candidate.len() != bubbled.len() {
+        let bubbled = bubbled.iter().map(|(c, _)| c).collect::<String>();
+        return similarity(candidate, &bubbled) * 0.5;
+    }
+    let total = bubbled.iter().map(|(_, conf)| conf).sum::<f32>();
+    let matching = candidate
+        .chars()
+        .zip(bubbled)
+        .filter(|(c, (b, _))| c == b)
+        .map(|(_, (_, conf))| *conf)
+        .collect::<Vec<_>>();
+    if total == 0.0 {
+        matching.len() as f32 / bubbled.len() as f32
+    } else {
+        matching.iter().sum::<f32>() / total
+    }
+}
+
+/// Picks the student ID from the bubbles, the OCR'd handwriting and the roster, if any. Returns
+/// the chosen ID, plus a review item when the sources could not be reconciled.
+pub fn reconcile_student_id(
+    columns: &[DigitColumn],
+    written: Option<&str>,
+    roster: Option<&[String]>,
+) -> (String, Option<IdReview>) {
+    let bubbled_with_confidence = bubbled_digits(columns);
+    let bubbled = bubbled_with_confidence
+        .iter()
+        .map(|(c, _)| c)
+        .collect::<String>();
+    let written = written.filter(|w| !w.is_empty());
+
+    // a written ID with unread digits can back up a candidate, but can't be one
+    let mut candidates = [Some(bubbled.as_str()), written]
+        .into_iter()
+        .flatten()
+        .filter(|c| !c.is_empty() && !c.contains(EMPTY_BOX))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if let Some(roster) = roster {
+        candidates.extend(
+            roster
+                .iter()
+                .filter(|id| {
+                    (!bubbled.is_empty() && edit_distance(&bubbled, id) <= MAX_CANDIDATE_DISTANCE)
+                        || written
+                            .is_some_and(|w| written_distance(w, id) <= MAX_CANDIDATE_DISTANCE)
+                })
+                .cloned(),
+        );
+    }
+    let fits_written = |id: &str| written.is_some_and(|w| written_distance(w, id) == 0);
+
+    let on_roster = |id: &str| roster.is_some_and(|r| r.iter().any(|r| r == id));
+    let scored = candidates
+        .into_iter()
+        .unique()
+        .map(|student_id| {
+            let mut score = BUBBLE_WEIGHT * bubble_score(&student_id, &bubbled_with_confidence)
+                + OCR_WEIGHT * written.map_or(0.0, |w| written_similarity(&student_id, w))
+                + ROSTER_WEIGHT * on_roster(&student_id) as u8 as f32;
+            if student_id.len() != STUDENT_ID_LEN {
+                score *= 0.5;
+            }
+            IdCandidate { student_id, score }
+        })
+        // stable sort, so the bubbled ID wins ties
+        .sorted_by(|a, b| b.score.total_cmp(&a.score))
+        .collect::<Vec<_>>();
+
+    let Some(best) = scored.first() else {
+        let review = IdReview {
+            reasons: vec![ReviewReason::InvalidLength],
+            bubbled: bubbled.clone(),
+            written: written.map(str::to_string),
+            chosen: bubbled.clone(),
+            candidates: vec![],
+        };
+        return (bubbled, Some(review));
+    };
+    let margin = best.score - scored.get(1).map_or(0.0, |c| c.score);
+
+    let mut reasons = vec![];
+    // the roster alone is never enough to replace what the student bubbled, as that could put
+    // the sheet under another student's ID
+    let chosen = if best.student_id != bubbled && !fits_written(&best.student_id) {
+        reasons.push(ReviewReason::NearRosterId);
+        bubbled.clone()
+    } else {
+        best.student_id.clone()
+    };
+    // a written ID with unread digits isn't a candidate that could come close, so any of its
+    // digits disagreeing with the chosen ID is enough
+    let partly_written = written.is_some_and(|w| w.contains(EMPTY_BOX));
+    if (written.is_some() && !fits_written(&bubbled) && margin < MIN_MARGIN)
+        || (partly_written && !fits_written(&chosen))
+    {
+        reasons.push(ReviewReason::BubbleOcrMismatch);
+    }
+    if roster.is_some() && !on_roster(&chosen) {
+        reasons.push(ReviewReason::NotOnRoster);
+    }
+    if chosen.len() != STUDENT_ID_LEN {
+        reasons.push(ReviewReason::InvalidLength);
+    }
+
+    let review = (!reasons.is_empty()).then(|| IdReview {
+        reasons,
+        bubbled,
+        written: written.map(str::to_string),
+        chosen: chosen.clone(),
+        candidates: scored.into_iter().take(3).collect(),
+    });
+    (chosen, review)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn columns(id: &str) -> Vec<DigitColumn> {
+        id.chars()
+            .map(|c| DigitColumn {
+                digit: c.to_digit(10).unwrap() as u8,
+                fill: 0.9,
+                runner_up_fill: 0.1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_agreeing_sources() {
+        let (id, review) = reconcile_student_id(&columns("165010003"), Some("65010003"), None);
+        assert_eq!(id, "65010003");
+        assert_eq!(review, None);
+    }
+
+    #[test]
+    fn test_short_bubbled_id_uses_written() {
+        let (id, review) = reconcile_student_id(&columns("6501003"), Some("65010003"), None);
+        assert_eq!(id, "65010003");
+        assert_eq!(review, None);
+    }
+
+    #[test]
+    fn test_mismatch_needs_review() {
+        let (id, review) = reconcile_student_id(&columns("65010003"), Some("65010008"), None);
+        assert_eq!(id, "65010003");
+        let review = review.unwrap();
+        assert_eq!(review.reasons, [ReviewReason::BubbleOcrMismatch]);
+        assert_eq!(review.candidates.len(), 2);
+    }
+
+    #[test]
+    fn test_roster_breaks_tie() {
+        let roster = ["65010008".to_string(), "65010120".to_string()];
+        let (id, review) =
+            reconcile_student_id(&columns("65010003"), Some("65010008"), Some(&roster));
+        assert_eq!(id, "65010008");
+        assert_eq!(review, None);
+    }
+
+    #[test]
+    fn test_not_on_roster() {
+        let roster = ["70000000".to_string()];
+        let (id, review) = reconcile_student_id(&columns("65010003"), None, Some(&roster));
+        assert_eq!(id, "65010003");
+        assert_eq!(review.unwrap().reasons, [ReviewReason::NotOnRoster]);
+    }
+
+    #[test]
+    fn test_near_roster_id_needs_review() {
+        for roster_id in ["65010008", "65010088"] {
+            let roster = [roster_id.to_string()];
+            let (id, review) = reconcile_student_id(&columns("65010003"), None, Some(&roster));
+            assert_eq!(id, "65010003");
+            let review = review.unwrap();
+            assert_eq!(
+                review.reasons,
+                [ReviewReason::NearRosterId, ReviewReason::NotOnRoster]
+            );
+            assert_eq!(review.chosen, "65010003");
+            assert_eq!(review.candidates[0].student_id, roster_id);
+        }
+
+        // equally near roster IDs don't get to pick between themselves either
+        let roster = ["65010008".to_string(), "65010009".to_string()];
+        let (id, review) = reconcile_student_id(&columns("65010003"), None, Some(&roster));
+        assert_eq!(id, "65010003");
+        assert!(review
+            .unwrap()
+            .reasons
+            .contains(&ReviewReason::NearRosterId));
+    }
+
+    #[test]
+    fn test_faint_digit_counts_less() {
+        let mut faint = columns("65010003");
+        faint[7].runner_up_fill = 0.85;
+        assert!(bubble_score("65010008", &bubbled_digits(&faint)) > 0.95);
+        assert!(bubble_score("65010008", &bubbled_digits(&columns("65010003"))) < 0.9);
+    }
+}
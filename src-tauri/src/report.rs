@@ -0,0 +1,289 @@
+use crate::err_log;
+use crate::{
+    errors::ExportError,
+    scoring::{AnswerSheetResult, CheckedAnswer},
+    signal,
+    state::{AnswerSheet, AppState, CsvExport},
+};
+use log::info;
+use opencv::{core::Mat, imgproc, prelude::*};
+use printpdf::{
+    BuiltinFont, ColorBits, ColorSpace, Image, ImageTransform, ImageXObject, IndirectFontRef, Mm,
+    PdfDocument, PdfDocumentReference, PdfLayerReference, Px,
+};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+use tauri::{ipc::Channel, Emitter, Manager, Runtime};
+use tauri_plugin_fs::FilePath;
+
+const PAGE_WIDTH: Mm = Mm(210.0);
+const PAGE_HEIGHT: Mm = Mm(297.0);
+const MARGIN: f32 = 15.0;
+/// The annotated sheet is scaled to fit in this box, under the header on the left.
+const IMAGE_BOX: (f32, f32) = (120.0, 225.0);
+const TABLE_X: f32 = 145.0;
+const LINE_HEIGHT: f32 = 6.0;
+
+/// Everything needed to render one student's page.
+pub struct ReportPage<'a> {
+    pub image: &'a Mat,
+    pub sheet: &'a AnswerSheet,
+    pub result: &'a AnswerSheetResult,
+    pub max_score: u32,
+}
+
+fn verdict_text(verdict: CheckedAnswer) -> &'static str {
+    match verdict {
+        CheckedAnswer::Correct => "Correct",
+        CheckedAnswer::Incorrect => "Incorrect",
+        CheckedAnswer::Missing => "Missing",
+        CheckedAnswer::NotCounted => "-",
+    }
+}
+
+/// Converts the annotated (BGR) sheet into an uncompressed RGB image for the PDF.
+fn mat_to_pdf_image(mat: &Mat) -> Result<Image, ExportError> {
+    let mut rgb = Mat::default();
+    let code = if mat.channels() == 1 {
+        imgproc::COLOR_GRAY2RGB
+    } else {
+        imgproc::COLOR_BGR2RGB
+    };
+    imgproc::cvt_color_def(mat, &mut rgb, code)?;
+    let rgb = if rgb.is_continuous() {
+        rgb
+    } else {
+        rgb.try_clone()?
+    };
+
+    Ok(Image::from(ImageXObject {
+        width: Px(rgb.cols() as usize),
+        height: Px(rgb.rows() as usize),
+        color_space: ColorSpace::Rgb,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data: rgb.data_bytes()?.to_vec(),
+        image_filter: None,
+        smask: None,
+        clipping_bbox: None,
+    }))
+}
+
+fn write_page(
+    layer: PdfLayerReference,
+    font: &IndirectFontRef,
+    bold: &IndirectFontRef,
+    page: &ReportPage,
+) -> Result<(), ExportError> {
+    let ReportPage {
+        image,
+        sheet,
+        result,
+        max_score,
+    } = page;
+    let top = PAGE_HEIGHT.0 - MARGIN;
+
+    layer.use_text("Score Report", 18.0, Mm(MARGIN), Mm(top), bold);
+    let details = [
+        format!(
+            "Student: {} ({})",
+            sheet.student_name.as_deref().unwrap_or("-"),
+            sheet.student_id
+        ),
+        format!(
+            "Subject: {} ({})",
+            sheet.subject_name.as_deref().unwrap_or("-"),
+            sheet.subject_id
+        ),
+        format!(
+            "Exam room: {}    Seat: {}",
+            sheet.exam_room.as_deref().unwrap_or("-"),
+            sheet.exam_seat.as_deref().unwrap_or("-")
+        ),
+    ];
+    for (idx, line) in details.iter().enumerate() {
+        let y = top - 9.0 - idx as f32 * LINE_HEIGHT;
+        layer.use_text(line, 11.0, Mm(MARGIN), Mm(y), font);
+    }
+    let score_y = top - 9.0 - details.len() as f32 * LINE_HEIGHT;
+    layer.use_text(
+        format!(
+            "Score: {} / {}    Correct: {}    Incorrect: {}",
+            result.score, max_score, result.correct, result.incorrect
+        ),
+        12.0,
+        Mm(MARGIN),
+        Mm(score_y),
+        bold,
+    );
+
+    // scale the sheet so it fits the box without distortion
+    let (width_px, height_px) = (image.cols() as f32, image.rows() as f32);
+    let dpi = f32::max(
+        width_px / (IMAGE_BOX.0 / 25.4),
+        height_px / (IMAGE_BOX.1 / 25.4),
+    );
+    let image_height = height_px / dpi * 25.4;
+    let image_top = score_y - LINE_HEIGHT;
+    mat_to_pdf_image(image)?.add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(MARGIN)),
+            translate_y: Some(Mm(image_top - image_height)),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+
+    let table_top = image_top - 4.0;
+    layer.use_text("No.", 9.0, Mm(TABLE_X), Mm(table_top), bold);
+    layer.use_text("Result", 9.0, Mm(TABLE_X + 10.0), Mm(table_top), bold);
+    layer.use_text("Points", 9.0, Mm(TABLE_X + 32.0), Mm(table_top), bold);
+    for (q, (checked, points)) in result.graded_questions.iter().enumerate() {
+        let y = Mm(table_top - (q as f32 + 1.0) * LINE_HEIGHT);
+        layer.use_text(format!("{:02}", q + 1), 9.0, Mm(TABLE_X), y, font);
+        layer.use_text(
+            verdict_text(checked.verdict()),
+            9.0,
+            Mm(TABLE_X + 10.0),
+            y,
+            font,
+        );
+        layer.use_text(points.to_string(), 9.0, Mm(TABLE_X + 32.0), y, font);
+    }
+    Ok(())
+}
+
+/// Renders one page per report into a single document.
+pub fn render_reports(pages: &[ReportPage]) -> Result<PdfDocumentReference, ExportError> {
+    let (doc, first_page, first_layer) =
+        PdfDocument::new("Score Report", PAGE_WIDTH, PAGE_HEIGHT, "Report");
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+
+    for (idx, page) in pages.iter().enumerate() {
+        let (page_idx, layer_idx) = if idx == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(PAGE_WIDTH, PAGE_HEIGHT, "Report")
+        };
+        let layer = doc.get_page(page_idx).get_layer(layer_idx);
+        write_page(layer, &font, &bold, page)?;
+    }
+    Ok(doc)
+}
+
+fn save(doc: PdfDocumentReference, path: &Path) -> Result<(), ExportError> {
+    doc.save(&mut BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+pub fn export_pdf_reports_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    per_student: bool,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    match export_pdf_reports_impl(app, path, per_student) {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+/// Writes every report into `path` as a single PDF, or as `{student_id}.pdf` files inside the
+/// `path` folder when `per_student` is set.
+pub fn export_pdf_reports_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+    per_student: bool,
+) -> Result<(), ExportError> {
+    let path = path.into_path()?;
+    info!("Exporting score reports to {}...", path.display());
+
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let max_score = AppState::get_max_score(app).ok_or(ExportError::IncorrectState)?;
+
+    let mut pages = results
+        .values()
+        .map(|(image, sheet, result)| ReportPage {
+            image,
+            sheet,
+            result,
+            max_score,
+        })
+        .collect::<Vec<_>>();
+    pages.sort_by(|a, b| a.sheet.student_id.cmp(&b.sheet.student_id));
+
+    if per_student {
+        for page in &pages {
+            let file: PathBuf = path.join(format!("{}.pdf", page.sheet.student_id));
+            save(render_reports(std::slice::from_ref(page))?, &file)?;
+        }
+    } else {
+        save(render_reports(&pages)?, &path)?;
+    }
+    info!("Finished exporting {} score reports!", pages.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::array;
+
+    use super::*;
+    use crate::{scoring::CheckedQuestionGroup, state::unit_tests::SheetBuilder};
+    use opencv::core;
+
+    #[test]
+    fn test_render_reports() {
+        let image =
+            Mat::new_rows_cols_with_default(40, 30, core::CV_8UC3, core::Scalar::all(255.0))
+                .unwrap();
+        let sheet = SheetBuilder::default()
+            .subject_name("Mathematics")
+            .student_name("Marcia Cole")
+            .build();
+        let result = AnswerSheetResult {
+            correct: 36,
+            incorrect: 0,
+            score: 36,
+            graded_questions: array::from_fn(|_| {
+                (
+                    CheckedQuestionGroup {
+                        A: CheckedAnswer::Correct,
+                        B: CheckedAnswer::NotCounted,
+                        C: CheckedAnswer::NotCounted,
+                        D: CheckedAnswer::NotCounted,
+                        E: CheckedAnswer::NotCounted,
+                    },
+                    1,
+                )
+            }),
+        };
+        let page = ReportPage {
+            image: &image,
+            sheet: &sheet,
+            result: &result,
+            max_score: 36,
+        };
+
+        let bytes = render_reports(&[page]).unwrap().save_to_bytes().unwrap();
+        assert_eq!(&bytes[0..5], b"%PDF-");
+    }
+}
@@ -0,0 +1,270 @@
+use crate::err_log;
+use crate::{
+    errors::RosterError,
+    signal,
+    state::{AnswerSheet, AppState, RosterImport},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fs::File, io::Read};
+use tauri::{ipc::Channel, Emitter, Manager, Runtime};
+use tauri_plugin_fs::FilePath;
+
+/// IDs at most this many edits away from a scanned ID are suggested as likely mis-bubbles.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterEntry {
+    #[serde(alias = "student_id", alias = "Student ID", alias = "id", alias = "ID")]
+    pub student_id: String,
+    #[serde(alias = "Name", alias = "student_name", alias = "Student Name")]
+    pub name: String,
+    #[serde(default, alias = "exam_room", alias = "Room", alias = "Exam Room")]
+    pub room: Option<String>,
+    #[serde(default, alias = "exam_seat", alias = "Seat", alias = "Exam Seat")]
+    pub seat: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdSuggestion {
+    pub student_id: String,
+    pub name: String,
+    /// Number of digits that have to be added, removed or changed to get this ID.
+    pub distance: usize,
+}
+
+/// A scanned sheet whose student ID is not on the roster.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownId {
+    pub student_id: String,
+    pub subject_id: String,
+    /// Closest absentees first.
+    pub suggestions: Vec<IdSuggestion>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterMerge {
+    pub matched: usize,
+    pub unknown_ids: Vec<UnknownId>,
+    /// Roster students without a scanned sheet.
+    pub absentees: Vec<RosterEntry>,
+}
+
+/// Reads a roster CSV with student ID, name, room and seat columns. Blank rooms and seats are
+/// read as `None` and rows without an ID are skipped.
+pub fn read_roster<T: Read>(reader: T) -> Result<Vec<RosterEntry>, RosterError> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .into_deserialize::<RosterEntry>()
+        .filter_map(|entry| match entry {
+            Ok(entry) if entry.student_id.is_empty() => None,
+            Ok(RosterEntry {
+                student_id,
+                name,
+                room,
+                seat,
+            }) => Some(Ok(RosterEntry {
+                student_id,
+                name,
+                room: room.filter(|r| !r.is_empty()),
+                seat: seat.filter(|s| !s.is_empty()),
+            })),
+            Err(e) => Some(Err(e.into())),
+        })
+        .collect()
+}
+
+/// Levenshtein distance between two IDs.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + (ca != *cb) as usize)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Joins scanned sheets against the roster. Names always come from the roster since the OCR'd
+/// ones are unreliable, while rooms and seats are only filled in when the roster has them.
+pub fn merge_roster<'a, I: IntoIterator<Item = &'a mut AnswerSheet>>(
+    roster: &[RosterEntry],
+    sheets: I,
+) -> RosterMerge {
+    let mut merge = RosterMerge::default();
+    let mut scanned = HashSet::new();
+    let mut unknown = vec![];
+
+    for sheet in sheets {
+        scanned.insert(sheet.student_id.clone());
+        match roster.iter().find(|e| e.student_id == sheet.student_id) {
+            Some(entry) => {
+                merge.matched += 1;
+                sheet.student_name = Some(entry.name.clone());
+                if entry.room.is_some() {
+                    sheet.exam_room = entry.room.clone();
+                }
+                if entry.seat.is_some() {
+                    sheet.exam_seat = entry.seat.clone();
+                }
+            }
+            None => unknown.push((sheet.student_id.clone(), sheet.subject_id.clone())),
+        }
+    }
+
+    merge.absentees = roster
+        .iter()
+        .filter(|e| !scanned.contains(&e.student_id))
+        .cloned()
+        .collect();
+    // a mis-bubbled ID shows up as both an unknown ID and an absentee, so only absentees are
+    // worth suggesting
+    merge.unknown_ids = unknown
+        .into_iter()
+        .map(|(student_id, subject_id)| {
+            let mut suggestions = merge
+                .absentees
+                .iter()
+                .map(|e| IdSuggestion {
+                    distance: edit_distance(&student_id, &e.student_id),
+                    student_id: e.student_id.clone(),
+                    name: e.name.clone(),
+                })
+                .filter(|s| s.distance <= MAX_SUGGESTION_DISTANCE)
+                .collect::<Vec<_>>();
+            suggestions.sort_by(|a, b| {
+                a.distance
+                    .cmp(&b.distance)
+                    .then_with(|| a.student_id.cmp(&b.student_id))
+            });
+            suggestions.truncate(MAX_SUGGESTIONS);
+            UnknownId {
+                student_id,
+                subject_id,
+                suggestions,
+            }
+        })
+        .collect();
+    merge
+        .unknown_ids
+        .sort_by(|a, b| a.student_id.cmp(&b.student_id));
+
+    merge
+}
+
+pub fn import_roster_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    channel: Channel<RosterImport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, RosterImport::Cancelled);
+        return;
+    };
+    match import_roster_impl(app, path) {
+        Ok(merge) => signal!(channel, RosterImport::Done { merge }),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                RosterImport::Error {
+                    error: format!("Error whilst trying to import roster: {e}")
+                }
+            )
+        }
+    }
+}
+
+pub fn import_roster_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+) -> Result<RosterMerge, RosterError> {
+    let path = path.into_path()?;
+    info!("Importing roster from {}...", path.display());
+    let roster = read_roster(File::open(path)?)?;
+    let students = roster.len();
+    let merge = AppState::merge_roster(app, roster);
+    info!(
+        "Merged roster of {students} students: {} matched, {} unknown, {} absent",
+        merge.matched,
+        merge.unknown_ids.len(),
+        merge.absentees.len()
+    );
+    Ok(merge)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::state::unit_tests::SheetBuilder;
+
+    fn sheet(student_id: &str) -> AnswerSheet {
+        SheetBuilder::default()
+            .student_id(student_id)
+            .student_name("Marica Col")
+            .exam_room("608")
+            .exam_seat("7")
+            .build()
+    }
+
+    const ROSTER: &str = "Student ID,Name,Room,Seat
+65010003, Marcia Cole ,,12
+65010009,Gwen Stacy,609,1
+65010038,Miles Morales,609,2
+";
+
+    #[test]
+    fn test_read_roster() {
+        let roster = read_roster(ROSTER.as_bytes()).unwrap();
+        assert_eq!(roster.len(), 3);
+        assert_eq!(
+            roster[0],
+            RosterEntry {
+                student_id: "65010003".to_string(),
+                name: "Marcia Cole".to_string(),
+                room: None,
+                seat: Some("12".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("65010003", "65010003"), 0);
+        assert_eq!(edit_distance("65010003", "65010008"), 1);
+        assert_eq!(edit_distance("6501003", "65010003"), 1);
+        assert_eq!(edit_distance("", "123"), 3);
+    }
+
+    #[test]
+    fn test_merge_roster() {
+        let roster = read_roster(ROSTER.as_bytes()).unwrap();
+        let mut sheets = [sheet("65010003"), sheet("65010030"), sheet("12345678")];
+        let merge = merge_roster(&roster, sheets.iter_mut());
+
+        assert_eq!(merge.matched, 1);
+        assert_eq!(sheets[0].student_name.as_deref(), Some("Marcia Cole"));
+        assert_eq!(sheets[0].exam_room.as_deref(), Some("608"));
+        assert_eq!(sheets[0].exam_seat.as_deref(), Some("12"));
+
+        assert_eq!(merge.absentees.len(), 2);
+        assert_eq!(merge.unknown_ids.len(), 2);
+        assert!(merge.unknown_ids[0].suggestions.is_empty());
+        let suggestions = &merge.unknown_ids[1].suggestions;
+        assert_eq!(suggestions[0].student_id, "65010038");
+        assert_eq!(suggestions[0].distance, 1);
+        assert_eq!(suggestions[1].student_id, "65010009");
+        assert_eq!(suggestions[1].distance, 2);
+    }
+}
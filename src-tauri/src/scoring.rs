@@ -4,6 +4,7 @@ use std::{collections::HashMap, fmt, fs::File, io::BufReader, mem};
 use csv::DeserializeRecordsIntoIter;
 use itertools::{multizip, Itertools};
 use log::{debug, error, warn};
+use serde::Serialize;
 use tauri::{ipc::Channel, Emitter, Manager, Runtime};
 use tauri_plugin_fs::FilePath;
 
@@ -59,7 +60,8 @@ impl CheckedQuestionGroup {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub enum CheckedAnswer {
     Correct,
     Incorrect,
@@ -81,7 +83,7 @@ impl Answer {
             (Some(_), None) | (None, None) => CheckedAnswer::NotCounted,
         }
     }
-    pub fn from_bubbles_iter<I: IntoIterator<Item = u8>>(iter: I) -> Option<Answer> {
+    pub fn read_bubbles_iter<I: IntoIterator<Item = u8>>(iter: I) -> BubbleRead {
         let mut num_type: Option<NumberType> = None;
         let mut num: Option<u8> = None;
 
@@ -95,25 +97,37 @@ impl Answer {
                         _ => unreachable!(),
                     });
                 } else {
-                    return None;
+                    return BubbleRead::MultipleMarks;
                 }
             } else if num.is_none() {
                 num = Some(idx - 3);
             } else {
                 debug!("found double circle");
-                return None;
+                return BubbleRead::MultipleMarks;
             }
         }
         match (num_type, num) {
-            (None, None) => None,
-            (None, Some(n)) => Some(Answer::Number(n)),
-            (Some(t), None) => Some(Answer::Type(t)),
-            (Some(t), Some(n)) => Some(Answer::Both(t, n)),
+            (None, None) => BubbleRead::Blank,
+            (None, Some(n)) => BubbleRead::Answer(Answer::Number(n)),
+            (Some(t), None) => BubbleRead::Answer(Answer::Type(t)),
+            (Some(t), Some(n)) => BubbleRead::Answer(Answer::Both(t, n)),
         }
     }
 }
 
+/// What was read off a single row of bubbles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BubbleRead {
+    Answer(Answer),
+    Blank,
+    /// More than one sign or more than one digit was filled in.
+    MultipleMarks,
+}
+
 impl QuestionGroup {
+    pub fn rows(&self) -> [Option<Answer>; 5] {
+        [self.A, self.B, self.C, self.D, self.E]
+    }
     pub fn check_with(&self, key: &Self) -> CheckedQuestionGroup {
         let arr = [
             Answer::check_with(self.A, key.A),
@@ -321,7 +335,9 @@ mod unit_tests {
     use std::array;
 
     use super::*;
-    use crate::state::{Answer, AnswerKeySheet, AnswerSheet, NumberType, QuestionGroup};
+    use crate::state::{
+        unit_tests::SheetBuilder, Answer, AnswerKeySheet, NumberType, QuestionGroup,
+    };
 
     fn answer(num: u8) -> Option<Answer> {
         Some(Answer::Number(num))
@@ -400,15 +416,11 @@ mod unit_tests {
             .try_into()
             .expect("12 * 3 is not 36");
 
-        let answer_sheet = AnswerSheet {
-            subject_id: 1001.to_string(),
-            student_id: 123456.to_string(),
-            answers,
-            subject_name: None,
-            student_name: None,
-            exam_room: None,
-            exam_seat: None,
-        };
+        let answer_sheet = SheetBuilder::default()
+            .subject_id("1001")
+            .student_id("123456")
+            .answers(answers)
+            .build();
 
         let key_sheet = AnswerKeySheet {
             subject_id: 1001.to_string(),
@@ -428,38 +440,51 @@ mod unit_tests {
     fn test_bubble_definite() {
         let bubbles = vec![3u8];
         let bubbles_plus = vec![0u8];
-        let ans = Answer::from_bubbles_iter(bubbles).unwrap();
-        let ans_plus = Answer::from_bubbles_iter(bubbles_plus).unwrap();
-
-        assert!(matches!(ans, Answer::Number(0u8)));
-        assert!(matches!(ans_plus, Answer::Type(NumberType::Plus)));
+        assert_eq!(
+            Answer::read_bubbles_iter(bubbles),
+            BubbleRead::Answer(Answer::Number(0u8))
+        );
+        assert_eq!(
+            Answer::read_bubbles_iter(bubbles_plus),
+            BubbleRead::Answer(Answer::Type(NumberType::Plus))
+        );
     }
     #[test]
     fn test_bubble_unclear() {
         let bubbles = vec![5u8, 8u8];
-        let ans = Answer::from_bubbles_iter(bubbles);
-        assert!(ans.is_none());
+        let ans = Answer::read_bubbles_iter(bubbles);
+        assert_eq!(ans, BubbleRead::MultipleMarks);
     }
     #[test]
     fn test_bubble_none() {
         let bubbles = vec![];
-        assert!(Answer::from_bubbles_iter(bubbles).is_none());
+        assert_eq!(Answer::read_bubbles_iter(bubbles), BubbleRead::Blank);
+    }
+    #[test]
+    fn test_bubble_multiple_signs() {
+        let bubbles = vec![0u8, 1u8, 5u8];
+        assert_eq!(
+            Answer::read_bubbles_iter(bubbles),
+            BubbleRead::MultipleMarks
+        );
     }
     #[test]
     fn test_bubble_plus_minus() {
         let bubbles_plus = vec![0u8, 5u8];
         let bubbles_minus = vec![1u8, 5u8];
         let bubbles_both = vec![2u8, 5u8];
-        let ans_plus = Answer::from_bubbles_iter(bubbles_plus).unwrap();
-        let ans_minus = Answer::from_bubbles_iter(bubbles_minus).unwrap();
-        let ans_both = Answer::from_bubbles_iter(bubbles_both).unwrap();
-
-        assert!(matches!(ans_plus, Answer::Both(NumberType::Plus, 2u8)));
-        assert!(matches!(ans_minus, Answer::Both(NumberType::Minus, 2u8)));
-        assert!(matches!(
-            ans_both,
-            Answer::Both(NumberType::PlusOrMinus, 2u8)
-        ));
+        assert_eq!(
+            Answer::read_bubbles_iter(bubbles_plus),
+            BubbleRead::Answer(Answer::Both(NumberType::Plus, 2u8))
+        );
+        assert_eq!(
+            Answer::read_bubbles_iter(bubbles_minus),
+            BubbleRead::Answer(Answer::Both(NumberType::Minus, 2u8))
+        );
+        assert_eq!(
+            Answer::read_bubbles_iter(bubbles_both),
+            BubbleRead::Answer(Answer::Both(NumberType::PlusOrMinus, 2u8))
+        );
     }
 
     #[test]
@@ -0,0 +1,378 @@
+use crate::{
+    errors::ExportError,
+    scoring::{AnswerSheetResult, CheckedAnswer},
+    state::{AnswerSheet, AppState},
+};
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tauri::{Emitter, Manager, Runtime};
+
+/// Percentiles reported in [`ScoreStatistics::percentiles`].
+const PERCENTILES: [u8; 5] = [10, 25, 50, 75, 90];
+/// Number of buckets the score range is split into for the histogram.
+const HISTOGRAM_BUCKETS: u32 = 10;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBucket {
+    /// Lowest score in this bucket (inclusive).
+    pub from: u32,
+    /// Highest score in this bucket (inclusive).
+    pub to: u32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Percentile {
+    pub percentile: u8,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreStatistics {
+    pub count: usize,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub std_dev: Option<f64>,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    pub percentiles: Vec<Percentile>,
+    pub histogram: Vec<HistogramBucket>,
+    /// Kuder-Richardson 20, computed on right/wrong per question.
+    pub kr20: Option<f64>,
+    /// Cronbach's alpha, computed on the points earned per question.
+    pub cronbach_alpha: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExamStatistics {
+    pub max_score: u32,
+    pub overall: ScoreStatistics,
+    pub by_subject: BTreeMap<String, ScoreStatistics>,
+    pub by_exam_room: BTreeMap<String, ScoreStatistics>,
+}
+
+pub fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Population variance.
+pub fn variance(values: &[f64]) -> Option<f64> {
+    let mean = mean(values)?;
+    Some(values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64)
+}
+
+/// Population standard deviation.
+pub fn std_dev(values: &[f64]) -> Option<f64> {
+    variance(values).map(f64::sqrt)
+}
+
+/// Linearly interpolated percentile of already sorted values, same as Excel's `PERCENTILE.INC`.
+fn percentile_of_sorted(sorted: &[f64], percentile: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = percentile / 100.0 * last as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+/// Splits `0..=max_score` into buckets. Bonus or weighted keys can give scores above `max_score`,
+/// so the last bucket reaches up to the highest score, and every score is in a bucket.
+fn histogram(scores: &[u32], max_score: u32) -> Vec<HistogramBucket> {
+    let points = max_score + 1;
+    let buckets = HISTOGRAM_BUCKETS.min(points);
+    let boundary = |bucket: u32| bucket * points / buckets;
+    let highest = scores.iter().copied().max().unwrap_or(0);
+    (0..buckets)
+        .map(|bucket| {
+            let from = boundary(bucket);
+            let to = if bucket + 1 == buckets {
+                max_score.max(highest)
+            } else {
+                boundary(bucket + 1) - 1
+            };
+            HistogramBucket {
+                from,
+                to,
+                count: scores.iter().filter(|s| (from..=to).contains(s)).count() as u32,
+            }
+        })
+        .collect()
+}
+
+/// `k / (k - 1) * (1 - sum of item variances / variance of totals)`, which is KR-20 when the
+/// items are scored 0 or 1 and Cronbach's alpha otherwise.
+fn reliability(items: &[Vec<f64>]) -> Option<f64> {
+    let k = items.len();
+    if k < 2 {
+        return None;
+    }
+    let totals = (0..items[0].len())
+        .map(|student| items.iter().map(|item| item[student]).sum())
+        .collect::<Vec<f64>>();
+    let total_variance = variance(&totals)?;
+    if total_variance == 0.0 {
+        return None;
+    }
+    let item_variances = items
+        .iter()
+        .map(|item| variance(item))
+        .sum::<Option<f64>>()?;
+    Some(k as f64 / (k - 1) as f64 * (1.0 - item_variances / total_variance))
+}
+
+pub fn score_statistics(results: &[&AnswerSheetResult], max_score: u32) -> ScoreStatistics {
+    let scores = results.iter().map(|r| r.score).collect::<Vec<_>>();
+    let sorted = scores
+        .iter()
+        .map(|s| *s as f64)
+        .sorted_by(f64::total_cmp)
+        .collect::<Vec<_>>();
+
+    // only questions the key has an answer for take part in reliability
+    let counted = (0..36)
+        .filter(|q| {
+            results
+                .iter()
+                .any(|r| r.graded_questions[*q].0.verdict() != CheckedAnswer::NotCounted)
+        })
+        .collect::<Vec<_>>();
+    let right_wrong = counted
+        .iter()
+        .map(|q| {
+            results
+                .iter()
+                .map(|r| {
+                    (r.graded_questions[*q].0.verdict() == CheckedAnswer::Correct) as u8 as f64
+                })
+                .collect()
+        })
+        .collect::<Vec<Vec<f64>>>();
+    let points = counted
+        .iter()
+        .map(|q| {
+            results
+                .iter()
+                .map(|r| r.graded_questions[*q].1 as f64)
+                .collect()
+        })
+        .collect::<Vec<Vec<f64>>>();
+
+    ScoreStatistics {
+        count: scores.len(),
+        mean: mean(&sorted),
+        median: percentile_of_sorted(&sorted, 50.0),
+        std_dev: std_dev(&sorted),
+        min: scores.iter().min().copied(),
+        max: scores.iter().max().copied(),
+        percentiles: PERCENTILES
+            .iter()
+            .filter_map(|p| {
+                percentile_of_sorted(&sorted, *p as f64).map(|score| Percentile {
+                    percentile: *p,
+                    score,
+                })
+            })
+            .collect(),
+        histogram: histogram(&scores, max_score),
+        kr20: reliability(&right_wrong),
+        cronbach_alpha: reliability(&points),
+    }
+}
+
+fn grouped_statistics<'a, F: Fn(&AnswerSheet) -> String>(
+    sheets: &[(&'a AnswerSheet, &'a AnswerSheetResult)],
+    max_score: u32,
+    group_by: F,
+) -> BTreeMap<String, ScoreStatistics> {
+    sheets
+        .iter()
+        .into_group_map_by(|(sheet, _)| group_by(*sheet))
+        .into_iter()
+        .map(|(group, sheets)| {
+            let results = sheets.into_iter().map(|(_, r)| *r).collect::<Vec<_>>();
+            (group, score_statistics(&results, max_score))
+        })
+        .collect()
+}
+
+pub fn exam_statistics<'a, I: IntoIterator<Item = (&'a AnswerSheet, &'a AnswerSheetResult)>>(
+    sheets: I,
+    max_score: u32,
+) -> ExamStatistics {
+    let sheets = sheets.into_iter().collect::<Vec<_>>();
+    let results = sheets.iter().map(|(_, r)| *r).collect::<Vec<_>>();
+
+    ExamStatistics {
+        max_score,
+        overall: score_statistics(&results, max_score),
+        by_subject: grouped_statistics(&sheets, max_score, |s| s.subject_id.clone()),
+        by_exam_room: grouped_statistics(&sheets, max_score, |s| {
+            s.exam_room.clone().unwrap_or_default()
+        }),
+    }
+}
+
+pub fn exam_statistics_of<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+) -> Result<ExamStatistics, ExportError> {
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let max_score = AppState::get_max_score(app).ok_or(ExportError::IncorrectState)?;
+    Ok(exam_statistics(
+        results.values().map(|(_, sheet, result)| (sheet, result)),
+        max_score,
+    ))
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::array;
+
+    use super::*;
+    use crate::{scoring::CheckedQuestionGroup, state::unit_tests::SheetBuilder};
+
+    fn checked(verdict: CheckedAnswer) -> CheckedQuestionGroup {
+        CheckedQuestionGroup {
+            A: verdict,
+            B: CheckedAnswer::NotCounted,
+            C: CheckedAnswer::NotCounted,
+            D: CheckedAnswer::NotCounted,
+            E: CheckedAnswer::NotCounted,
+        }
+    }
+
+    /// A result where only the first 4 questions are counted, with `right` of them correct.
+    fn result_with(right: usize) -> AnswerSheetResult {
+        AnswerSheetResult {
+            correct: right as u32,
+            incorrect: 4 - right as u32,
+            score: right as u32,
+            graded_questions: array::from_fn(|q| match q {
+                q if q < right => (checked(CheckedAnswer::Correct), 1),
+                q if q < 4 => (checked(CheckedAnswer::Incorrect), 0),
+                _ => (checked(CheckedAnswer::NotCounted), 0),
+            }),
+        }
+    }
+
+    fn sheet_in_room(room: &str) -> AnswerSheet {
+        SheetBuilder::default().exam_room(room).build()
+    }
+
+    #[test]
+    fn test_basic_statistics() {
+        let results = [
+            result_with(1),
+            result_with(2),
+            result_with(3),
+            result_with(4),
+        ];
+        let stats = score_statistics(&results.iter().collect::<Vec<_>>(), 4);
+
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.mean, Some(2.5));
+        assert_eq!(stats.median, Some(2.5));
+        assert_eq!(stats.min, Some(1));
+        assert_eq!(stats.max, Some(4));
+        assert!((stats.std_dev.unwrap() - 1.25f64.sqrt()).abs() < 1e-9);
+        assert_eq!(
+            stats.percentiles[1],
+            Percentile {
+                percentile: 25,
+                score: 1.75
+            }
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        let buckets = histogram(&[0, 3, 4, 4, 19, 20], 20);
+        assert_eq!(buckets.len(), 10);
+        assert_eq!(
+            buckets[0],
+            HistogramBucket {
+                from: 0,
+                to: 1,
+                count: 1
+            }
+        );
+        assert_eq!(buckets[2].count, 2);
+        assert_eq!(
+            buckets[9],
+            HistogramBucket {
+                from: 18,
+                to: 20,
+                count: 2
+            }
+        );
+        assert_eq!(histogram(&[2], 3).len(), 4);
+    }
+
+    #[test]
+    fn test_histogram_above_max_score() {
+        let scores = [0, 10, 20, 22, 25];
+        let buckets = histogram(&scores, 20);
+        assert_eq!(
+            buckets.iter().map(|b| b.count).sum::<u32>(),
+            scores.len() as u32
+        );
+        assert_eq!(
+            buckets[9],
+            HistogramBucket {
+                from: 18,
+                to: 25,
+                count: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_reliability() {
+        let results = [
+            result_with(1),
+            result_with(2),
+            result_with(3),
+            result_with(4),
+        ];
+        let stats = score_statistics(&results.iter().collect::<Vec<_>>(), 4);
+        let kr20 = stats.kr20.unwrap();
+        assert!((kr20 - 2.0 / 3.0).abs() < 1e-9, "{kr20}");
+        // with weights of 1, alpha is the same as KR-20
+        assert_eq!(stats.kr20, stats.cronbach_alpha);
+
+        // everyone scored the same, so there is no variance to explain
+        let results = [result_with(2), result_with(2)];
+        let stats = score_statistics(&results.iter().collect::<Vec<_>>(), 4);
+        assert_eq!(stats.kr20, None);
+    }
+
+    #[test]
+    fn test_empty_statistics() {
+        let stats = score_statistics(&[], 10);
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.mean, None);
+        assert_eq!(stats.median, None);
+        assert!(stats.percentiles.is_empty());
+        assert!(stats.histogram.iter().all(|b| b.count == 0));
+    }
+
+    #[test]
+    fn test_grouped_by_room() {
+        let sheets = [
+            sheet_in_room("608"),
+            sheet_in_room("608"),
+            sheet_in_room("609"),
+        ];
+        let results = [result_with(1), result_with(3), result_with(4)];
+        let stats = exam_statistics(sheets.iter().zip(results.iter()), 4);
+
+        assert_eq!(stats.overall.count, 3);
+        assert_eq!(stats.by_subject.len(), 1);
+        assert_eq!(stats.by_subject["10"].count, 3);
+        assert_eq!(stats.by_exam_room["608"].mean, Some(2.0));
+        assert_eq!(stats.by_exam_room["609"].mean, Some(4.0));
+    }
+}
@@ -1,20 +1,48 @@
+use crate::analysis::{self, ItemAnalysis};
 use crate::err_log;
-use crate::state::{MongoDB, Options};
+use crate::grading::{Grade, GradingScale};
+use crate::image::mat_to_webp;
+use crate::state::{Options, Storage, SYNC_QUEUE_LOCK};
+use crate::statistics::{self, ExamStatistics, ScoreStatistics};
 use crate::{
-    errors::ExportError,
-    scoring::AnswerSheetResult,
+    errors::{ConnectionError, ExportError, StorageError, SyncError},
+    scoring::{AnswerSheetResult, CheckedAnswer, ScoreWeights},
     signal,
-    state::{AnswerSheet, AppState, CsvExport},
+    state::{Answer, AnswerKeySheet, AnswerSheet, AppState, CsvExport, DbSync, NumberType},
 };
 use log::info;
+use native_tls::TlsConnector;
 use opencv::prelude::Mat;
+use postgres_native_tls::MakeTlsConnector;
+use rusqlite::params;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
 use serde::ser::SerializeStruct;
-use serde::Serialize;
-use std::{collections::HashMap, fs::File};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tauri::{ipc::Channel, Emitter, Manager, Runtime};
 use tauri_plugin_fs::FilePath;
 
-use mongodb::{bson::doc, options::ClientOptions, Client};
+use futures::AsyncWriteExt;
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    gridfs::GridFsBucket,
+    options::{ClientOptions, GridFsBucketOptions},
+    Client, Database,
+};
+
+/// Headers of the question columns, shared by the CSV and XLSX exports so they line up.
+const QUESTION_COLUMNS: [&str; 36] = [
+    "01", "02", "03", "04", "05", "06", "07", "08", "09", "10", "11", "12", "13", "14", "15", "16",
+    "17", "18", "19", "20", "21", "22", "23", "24", "25", "26", "27", "28", "29", "30", "31", "32",
+    "33", "34", "35", "36",
+];
 
 #[allow(non_snake_case)]
 #[derive(Debug, Clone)]
@@ -27,6 +55,7 @@ pub struct QuestionScoreRow {
     pub exam_seat: String,
     questions: Vec<String>,
     total_score: String,
+    grade: Option<Grade>,
 }
 
 impl serde::Serialize for QuestionScoreRow {
@@ -34,7 +63,8 @@ impl serde::Serialize for QuestionScoreRow {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("QuestionScoreRow", 36 + 6)?;
+        let grade_fields = if self.grade.is_some() { 3 } else { 0 };
+        let mut state = serializer.serialize_struct("QuestionScoreRow", 36 + 6 + grade_fields)?;
         state.serialize_field("subject_id", &self.subject_id)?;
         state.serialize_field("student_id", &self.student_id)?;
         state.serialize_field("subject_name", &self.subject_name)?;
@@ -42,48 +72,28 @@ impl serde::Serialize for QuestionScoreRow {
         state.serialize_field("exam_room", &self.exam_room)?;
         state.serialize_field("exam_seat", &self.exam_seat)?;
 
-        state.serialize_field("01", &self.questions[0])?;
-        state.serialize_field("02", &self.questions[1])?;
-        state.serialize_field("03", &self.questions[2])?;
-        state.serialize_field("04", &self.questions[3])?;
-        state.serialize_field("05", &self.questions[4])?;
-        state.serialize_field("06", &self.questions[5])?;
-        state.serialize_field("07", &self.questions[6])?;
-        state.serialize_field("08", &self.questions[7])?;
-        state.serialize_field("09", &self.questions[8])?;
-        state.serialize_field("10", &self.questions[9])?;
-        state.serialize_field("11", &self.questions[10])?;
-        state.serialize_field("12", &self.questions[11])?;
-        state.serialize_field("13", &self.questions[12])?;
-        state.serialize_field("14", &self.questions[13])?;
-        state.serialize_field("15", &self.questions[14])?;
-        state.serialize_field("16", &self.questions[15])?;
-        state.serialize_field("17", &self.questions[16])?;
-        state.serialize_field("18", &self.questions[17])?;
-        state.serialize_field("19", &self.questions[18])?;
-        state.serialize_field("20", &self.questions[19])?;
-        state.serialize_field("21", &self.questions[20])?;
-        state.serialize_field("22", &self.questions[21])?;
-        state.serialize_field("23", &self.questions[22])?;
-        state.serialize_field("24", &self.questions[23])?;
-        state.serialize_field("25", &self.questions[24])?;
-        state.serialize_field("26", &self.questions[25])?;
-        state.serialize_field("27", &self.questions[26])?;
-        state.serialize_field("28", &self.questions[27])?;
-        state.serialize_field("29", &self.questions[28])?;
-        state.serialize_field("30", &self.questions[29])?;
-        state.serialize_field("31", &self.questions[30])?;
-        state.serialize_field("32", &self.questions[31])?;
-        state.serialize_field("33", &self.questions[32])?;
-        state.serialize_field("34", &self.questions[33])?;
-        state.serialize_field("35", &self.questions[34])?;
-        state.serialize_field("36", &self.questions[35])?;
+        for (column, answer) in QUESTION_COLUMNS.into_iter().zip(&self.questions) {
+            state.serialize_field(column, answer)?;
+        }
         state.serialize_field("total_score", &self.total_score)?;
+        if let Some(Grade {
+            percentage,
+            curved_percentage,
+            grade,
+        }) = &self.grade
+        {
+            state.serialize_field("percentage", &format!("{percentage:.2}"))?;
+            state.serialize_field(
+                "curved_percentage",
+                &curved_percentage.map(|p| format!("{p:.2}")),
+            )?;
+            state.serialize_field("grade", grade)?;
+        }
         state.end()
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StudentTotalScore {
     pub subject_id: String,
     pub student_id: String,
@@ -92,6 +102,147 @@ pub struct StudentTotalScore {
     pub exam_room: String,
     pub exam_seat: String,
     pub total_score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub curved_percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grade: Option<String>,
+}
+
+/// Version of the JSON export format. Bumped whenever a field is removed or changes meaning.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// A single bubbled answer.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnswerRecord {
+    /// `"plus"`, `"minus"` or `"plusOrMinus"`, if a sign was bubbled.
+    pub sign: Option<NumberType>,
+    /// 0-9, if a digit was bubbled.
+    pub digit: Option<u8>,
+    /// Human readable form, e.g. `"-3"`.
+    pub text: String,
+}
+impl From<Answer> for AnswerRecord {
+    fn from(answer: Answer) -> Self {
+        let (sign, digit) = match answer {
+            Answer::Type(t) => (Some(t), None),
+            Answer::Number(n) => (None, Some(n)),
+            Answer::Both(t, n) => (Some(t), Some(n)),
+        };
+        Self {
+            sign,
+            digit,
+            text: answer.to_string(),
+        }
+    }
+}
+
+/// One of the rows A to E of a question.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowRecord {
+    /// `"A"` to `"E"`.
+    pub row: char,
+    /// What the student bubbled, `null` if blank or unreadable.
+    pub answer: Option<AnswerRecord>,
+    /// What the key says, `null` if this row is not counted.
+    pub key: Option<AnswerRecord>,
+    /// Whether more than one bubble was filled in on this row.
+    pub multiple_marks: bool,
+    /// `"correct"`, `"incorrect"`, `"missing"` or `"notCounted"`.
+    pub verdict: CheckedAnswer,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuestionRecord {
+    /// 1-based question number, as printed on the sheet.
+    pub question: u8,
+    /// Verdict for the whole question, only `"correct"` if every counted row is correct.
+    pub verdict: CheckedAnswer,
+    /// Points earned for this question.
+    pub points: u8,
+    /// Points this question is worth.
+    pub weight: u8,
+    pub rows: Vec<RowRecord>,
+}
+
+/// Everything known about a scored sheet. This is one element of the JSON array, or one line of
+/// the JSON Lines file.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetRecord {
+    /// See [`JSON_SCHEMA_VERSION`].
+    pub schema_version: u32,
+    pub subject_id: String,
+    pub student_id: String,
+    /// OCR fields, `null` if OCR was disabled.
+    pub subject_name: Option<String>,
+    pub student_name: Option<String>,
+    pub exam_room: Option<String>,
+    pub exam_seat: Option<String>,
+    pub score: u32,
+    pub max_score: u32,
+    pub correct: u32,
+    pub incorrect: u32,
+    /// Always 36 entries.
+    pub questions: Vec<QuestionRecord>,
+}
+
+pub fn sheet_record(
+    sheet: &AnswerSheet,
+    result: &AnswerSheetResult,
+    key: &AnswerKeySheet,
+    weights: &ScoreWeights,
+) -> SheetRecord {
+    let (question_weights, max_score) = weights
+        .weights
+        .get(&sheet.subject_id)
+        .map(|(w, max_score)| (w.as_slice(), max_score - weights.max_score_deduction(key)))
+        .unwrap_or_default();
+
+    let questions = result
+        .graded_questions
+        .iter()
+        .enumerate()
+        .map(|(q, (checked, points))| QuestionRecord {
+            question: q as u8 + 1,
+            verdict: checked.verdict(),
+            points: *points,
+            weight: question_weights.get(q).copied().unwrap_or_default(),
+            rows: sheet.answers[q]
+                .rows()
+                .into_iter()
+                .zip(key.answers[q].rows())
+                .zip('A'..='E')
+                .enumerate()
+                .map(|(r, ((answer, key), row))| RowRecord {
+                    row,
+                    answer: answer.map(AnswerRecord::from),
+                    key: key.map(AnswerRecord::from),
+                    multiple_marks: sheet.multi_marked[q][r],
+                    verdict: checked.at(r).expect("checked answer < 5"),
+                })
+                .collect(),
+        })
+        .collect();
+
+    SheetRecord {
+        schema_version: JSON_SCHEMA_VERSION,
+        subject_id: sheet.subject_id.clone(),
+        student_id: sheet.student_id.clone(),
+        subject_name: sheet.subject_name.clone(),
+        student_name: sheet.student_name.clone(),
+        exam_room: sheet.exam_room.clone(),
+        exam_seat: sheet.exam_seat.clone(),
+        score: result.score,
+        max_score,
+        correct: result.correct,
+        incorrect: result.incorrect,
+        questions,
+    }
 }
 
 pub fn export_to_csv_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
@@ -127,8 +278,10 @@ pub fn export_to_csv_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
     let mut wtr = csv::Writer::from_writer(file);
 
     let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let Options { grading, .. } = AppState::get_options(app);
+    let max_score = AppState::get_max_score(app).ok_or(ExportError::IncorrectState)?;
 
-    let question_rows = map_to_csv(results);
+    let question_rows = map_to_csv(results, grading.as_ref().map(|g| (g, max_score)));
     let len = question_rows.len();
 
     for row in &question_rows {
@@ -136,17 +289,358 @@ pub fn export_to_csv_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
     }
     wtr.flush()?;
     info!("Finished exporting to CSV! Written {len} rows.");
-    let student_totals = map_to_db_scores(question_rows);
 
-    store_scores_in_db(app, student_totals)?;
+    Ok(())
+}
+
+pub fn export_to_xlsx_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    match export_to_xlsx_impl(app, path) {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+pub fn export_to_xlsx_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+) -> Result<(), ExportError> {
+    let path = path.into_path()?;
+    info!("Exporting scanned results to {}...", path.display());
+
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let Options { grading, .. } = AppState::get_options(app);
+    let max_score = AppState::get_max_score(app).ok_or(ExportError::IncorrectState)?;
+
+    let stats = statistics::exam_statistics(
+        results.values().map(|(_, sheet, result)| (sheet, result)),
+        max_score,
+    );
+    let items = analysis::analyze_items(results.values().map(|(_, _, result)| result));
+    let question_rows = map_to_csv(results, grading.as_ref().map(|g| (g, max_score)));
 
+    let mut workbook = build_workbook(&question_rows, &stats, &items)?;
+    workbook.save(path)?;
+    info!(
+        "Finished exporting to XLSX! Written {} rows.",
+        question_rows.len()
+    );
+
+    Ok(())
+}
+
+fn write_header(worksheet: &mut Worksheet, headers: &[&str]) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    for (col, header) in headers.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, *header, &bold)?;
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+/// Writes a number if it parses as one, so Excel can do math on it, and a string otherwise.
+fn write_cell(worksheet: &mut Worksheet, row: u32, col: u16, value: &str) -> Result<(), XlsxError> {
+    match value.parse::<f64>() {
+        Ok(number) => worksheet.write_number(row, col, number)?,
+        Err(_) => worksheet.write_string(row, col, value)?,
+    };
+    Ok(())
+}
+
+fn write_optional(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    value: Option<f64>,
+) -> Result<(), XlsxError> {
+    if let Some(value) = value {
+        worksheet.write_number(row, col, value)?;
+    }
+    Ok(())
+}
+
+/// The same columns as [`QuestionScoreRow`] has in the CSV export.
+fn scores_headers(with_grade: bool) -> Vec<&'static str> {
+    let mut headers = vec![
+        "subject_id",
+        "student_id",
+        "subject_name",
+        "student_name",
+        "exam_room",
+        "exam_seat",
+    ];
+    headers.extend(QUESTION_COLUMNS);
+    headers.push("total_score");
+    if with_grade {
+        headers.extend(["percentage", "curved_percentage", "grade"]);
+    }
+    headers
+}
+
+fn scores_worksheet(rows: &[QuestionScoreRow]) -> Result<Worksheet, XlsxError> {
+    let mut worksheet = Worksheet::new();
+    worksheet.set_name("Scores")?;
+
+    let with_grade = rows.iter().any(|r| r.grade.is_some());
+    write_header(&mut worksheet, &scores_headers(with_grade))?;
+
+    for (idx, row) in rows.iter().enumerate() {
+        let r = idx as u32 + 1;
+        // IDs are kept as text so leading zeroes survive
+        worksheet.write_string(r, 0, &row.subject_id)?;
+        worksheet.write_string(r, 1, &row.student_id)?;
+        worksheet.write_string(r, 2, &row.subject_name)?;
+        worksheet.write_string(r, 3, &row.student_name)?;
+        worksheet.write_string(r, 4, &row.exam_room)?;
+        worksheet.write_string(r, 5, &row.exam_seat)?;
+        for (q, points) in row.questions.iter().enumerate() {
+            write_cell(&mut worksheet, r, 6 + q as u16, points)?;
+        }
+        write_cell(&mut worksheet, r, 42, &row.total_score)?;
+        if let Some(grade) = &row.grade {
+            worksheet.write_number(r, 43, grade.percentage)?;
+            write_optional(&mut worksheet, r, 44, grade.curved_percentage)?;
+            worksheet.write_string(r, 45, &grade.grade)?;
+        }
+    }
+    Ok(worksheet)
+}
+
+fn statistics_worksheet(stats: &ExamStatistics) -> Result<Worksheet, XlsxError> {
+    let mut worksheet = Worksheet::new();
+    worksheet.set_name("Statistics")?;
+    write_header(
+        &mut worksheet,
+        &[
+            "group_by",
+            "group",
+            "count",
+            "mean",
+            "median",
+            "std_dev",
+            "min",
+            "max",
+            "p10",
+            "p25",
+            "p50",
+            "p75",
+            "p90",
+            "kr20",
+            "cronbach_alpha",
+        ],
+    )?;
+
+    let groups = [("overall", String::new(), &stats.overall)]
+        .into_iter()
+        .chain(
+            stats
+                .by_subject
+                .iter()
+                .map(|(group, s)| ("subject", group.clone(), s)),
+        )
+        .chain(
+            stats
+                .by_exam_room
+                .iter()
+                .map(|(group, s)| ("exam_room", group.clone(), s)),
+        );
+    for (idx, (group_by, group, s)) in groups.enumerate() {
+        let r = idx as u32 + 1;
+        let ScoreStatistics {
+            count,
+            mean,
+            median,
+            std_dev,
+            min,
+            max,
+            percentiles,
+            kr20,
+            cronbach_alpha,
+            ..
+        } = s;
+        worksheet.write_string(r, 0, group_by)?;
+        worksheet.write_string(r, 1, group)?;
+        worksheet.write_number(r, 2, *count as f64)?;
+        write_optional(&mut worksheet, r, 3, *mean)?;
+        write_optional(&mut worksheet, r, 4, *median)?;
+        write_optional(&mut worksheet, r, 5, *std_dev)?;
+        write_optional(&mut worksheet, r, 6, min.map(f64::from))?;
+        write_optional(&mut worksheet, r, 7, max.map(f64::from))?;
+        for (col, percentile) in percentiles.iter().enumerate() {
+            worksheet.write_number(r, 8 + col as u16, percentile.score)?;
+        }
+        write_optional(&mut worksheet, r, 13, *kr20)?;
+        write_optional(&mut worksheet, r, 14, *cronbach_alpha)?;
+    }
+    Ok(worksheet)
+}
+
+fn histogram_worksheet(stats: &ExamStatistics) -> Result<Worksheet, XlsxError> {
+    let mut worksheet = Worksheet::new();
+    worksheet.set_name("Histogram")?;
+    write_header(&mut worksheet, &["from", "to", "count"])?;
+    for (idx, bucket) in stats.overall.histogram.iter().enumerate() {
+        let r = idx as u32 + 1;
+        worksheet.write_number(r, 0, bucket.from)?;
+        worksheet.write_number(r, 1, bucket.to)?;
+        worksheet.write_number(r, 2, bucket.count)?;
+    }
+    Ok(worksheet)
+}
+
+fn items_worksheet(items: &[ItemAnalysis]) -> Result<Worksheet, XlsxError> {
+    let mut worksheet = Worksheet::new();
+    worksheet.set_name("Questions")?;
+    write_header(
+        &mut worksheet,
+        &[
+            "question",
+            "counted",
+            "correct",
+            "incorrect",
+            "missing",
+            "difficulty",
+            "point_biserial",
+            "discrimination",
+            "a_difficulty",
+            "b_difficulty",
+            "c_difficulty",
+            "d_difficulty",
+            "e_difficulty",
+        ],
+    )?;
+    for (idx, item) in items.iter().enumerate() {
+        let r = idx as u32 + 1;
+        worksheet.write_number(r, 0, item.question)?;
+        worksheet.write_number(r, 1, item.counts.counted())?;
+        worksheet.write_number(r, 2, item.counts.correct)?;
+        worksheet.write_number(r, 3, item.counts.incorrect)?;
+        worksheet.write_number(r, 4, item.counts.missing)?;
+        write_optional(&mut worksheet, r, 5, item.difficulty)?;
+        write_optional(&mut worksheet, r, 6, item.point_biserial)?;
+        write_optional(&mut worksheet, r, 7, item.discrimination)?;
+        for (col, row) in item.rows.iter().enumerate() {
+            write_optional(&mut worksheet, r, 8 + col as u16, row.difficulty)?;
+        }
+    }
+    Ok(worksheet)
+}
+
+fn build_workbook(
+    rows: &[QuestionScoreRow],
+    stats: &ExamStatistics,
+    items: &[ItemAnalysis],
+) -> Result<Workbook, XlsxError> {
+    let mut workbook = Workbook::new();
+    workbook.push_worksheet(scores_worksheet(rows)?);
+    workbook.push_worksheet(statistics_worksheet(stats)?);
+    workbook.push_worksheet(histogram_worksheet(stats)?);
+    workbook.push_worksheet(items_worksheet(items)?);
+    Ok(workbook)
+}
+
+pub fn export_to_json_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: Option<FilePath>,
+    channel: Channel<CsvExport>,
+) {
+    let Some(path) = path else {
+        signal!(channel, CsvExport::Cancelled);
+        return;
+    };
+    match export_to_json_impl(app, path) {
+        Ok(_) => signal!(channel, CsvExport::Done),
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                CsvExport::Error {
+                    error: format!("Error whilst trying to export: {e}")
+                }
+            )
+        }
+    }
+}
+
+/// Writes a JSON array of [`SheetRecord`]s, or JSON Lines if the file ends with `.jsonl`.
+pub fn export_to_json_impl<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    path: FilePath,
+) -> Result<(), ExportError> {
+    let path = path.into_path()?;
+    info!("Exporting full results to {}...", path.display());
+
+    let results = AppState::get_scored_answers(app).ok_or(ExportError::IncorrectState)?;
+    let key = AppState::get_key(app).ok_or(ExportError::IncorrectState)?;
+    let weights = AppState::get_weights(app).ok_or(ExportError::IncorrectState)?;
+
+    let mut records = results
+        .values()
+        .map(|(_, sheet, result)| sheet_record(sheet, result, &key, &weights))
+        .collect::<Vec<_>>();
+    records.sort_by(|a, b| a.student_id.cmp(&b.student_id));
+
+    let lines = path.extension().is_some_and(|ext| ext == "jsonl");
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_json(&mut writer, &records, lines)?;
+    writer.flush()?;
+    info!(
+        "Finished exporting to JSON! Written {} sheets.",
+        records.len()
+    );
+
+    Ok(())
+}
+
+fn write_json<W: Write>(
+    writer: &mut W,
+    records: &[SheetRecord],
+    lines: bool,
+) -> Result<(), ExportError> {
+    if lines {
+        for record in records {
+            serde_json::to_writer(&mut *writer, record)?;
+            writeln!(writer)?;
+        }
+    } else {
+        serde_json::to_writer_pretty(writer, records)?;
+    }
     Ok(())
 }
 
 fn map_to_csv(
     map: HashMap<String, (Mat, AnswerSheet, AnswerSheetResult)>,
+    grading: Option<(&GradingScale, u32)>,
 ) -> Vec<QuestionScoreRow> {
-    map.into_iter()
+    let entries = map.into_iter().collect::<Vec<_>>();
+    let mut grades = grading
+        .map(|(scale, max_score)| {
+            let scores = entries
+                .iter()
+                .map(|(_, (_, _, result))| result.score)
+                .collect::<Vec<_>>();
+            scale.grade_all(&scores, max_score)
+        })
+        .unwrap_or_default()
+        .into_iter();
+
+    entries
+        .into_iter()
         .map(
             |(
                 student_id,
@@ -181,6 +675,7 @@ fn map_to_csv(
                     exam_seat: exam_seat.clone().unwrap_or_default(),
                     questions: graded,
                     total_score: score.to_string(),
+                    grade: grades.next(),
                 }
             },
         )
@@ -198,6 +693,15 @@ pub fn map_to_db_scores(question_score_rows: Vec<QuestionScoreRow>) -> Vec<Stude
                 total += ans.parse::<f32>().unwrap_or(0.0);
             }
 
+            let (percentage, curved_percentage, grade) = match row.grade {
+                Some(Grade {
+                    percentage,
+                    curved_percentage,
+                    grade,
+                }) => (Some(percentage), curved_percentage, Some(grade)),
+                None => (None, None, None),
+            };
+
             StudentTotalScore {
                 subject_id: row.subject_id,
                 student_id: row.student_id,
@@ -206,50 +710,1210 @@ pub fn map_to_db_scores(question_score_rows: Vec<QuestionScoreRow>) -> Vec<Stude
                 exam_room: row.exam_room,
                 exam_seat: row.exam_seat,
                 total_score: total,
+                percentage,
+                curved_percentage,
+                grade,
             }
         })
         .collect()
 }
 
-fn store_scores_in_db<R: Runtime, A: Emitter<R> + Manager<R>>(
+/// Rows are sent to the database in batches of this size.
+const SYNC_BATCH_SIZE: usize = 500;
+const TOTAL_SCORES_COLLECTION: &str = "student_total_scores";
+const SHEET_RESULTS_COLLECTION: &str = "sheet_results";
+const SHEET_IMAGES_BUCKET: &str = "sheet_images";
+/// How long to wait for a PostgreSQL server, or for a MongoDB server to be selected.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What happened to the documents of one collection.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub failed: usize,
+    pub errors: Vec<String>,
+}
+impl SyncCounts {
+    fn add(&mut self, other: SyncCounts) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.failed += other.failed;
+        self.errors.extend(other.errors);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSummary {
+    /// Nothing was written; inserted and updated are what would have happened.
+    pub dry_run: bool,
+    pub totals: SyncCounts,
+    /// Only set when details were synced.
+    pub sheet_results: Option<SyncCounts>,
+    pub images_uploaded: usize,
+}
+
+/// Item-level results of a sheet, stored in `sheet_results` next to the totals. Every row of the
+/// record already carries the bubbled answer, the key's answer and the verdict.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetResultDocument {
+    #[serde(flatten)]
+    pub record: SheetRecord,
+    /// The exam the sheet belongs to, see [`sync_scores`].
+    pub exam: String,
+    /// Identifies the weights the sheet was scored with, see [`weights_version`].
+    pub weights_version: String,
+    /// `_id` of the annotated image in the `sheet_images` GridFS bucket.
+    pub image_id: String,
+}
+
+/// First 16 hex digits of the SHA-256 of the weights, so results scored with different weights
+/// can be told apart.
+pub fn weights_version(weights: &[u8]) -> String {
+    let hash = Sha256::digest(weights);
+    hash.iter().take(8).map(|b| format!("{b:02x}")).collect()
+}
+
+fn image_id_of(exam: &str, sheet: &AnswerSheet) -> String {
+    format!("{exam}_{}_{}", sheet.subject_id, sheet.student_id)
+}
+
+pub fn sheet_result_document(
+    exam: &str,
+    sheet: &AnswerSheet,
+    result: &AnswerSheetResult,
+    key: &AnswerKeySheet,
+    weights: &ScoreWeights,
+) -> SheetResultDocument {
+    SheetResultDocument {
+        record: sheet_record(sheet, result, key, weights),
+        exam: exam.to_string(),
+        weights_version: weights
+            .weights
+            .get(&sheet.subject_id)
+            .map(|(w, _)| weights_version(w))
+            .unwrap_or_default(),
+        image_id: image_id_of(exam, sheet),
+    }
+}
+
+/// Total scores of every scored sheet, graded if a grading scale is set.
+pub fn student_totals_of<R: Runtime, A: Emitter<R> + Manager<R>>(
     app: &A,
-    rows: Vec<StudentTotalScore>,
-) -> Result<(), ExportError> {
-    if let Options {
-        mongo: MongoDB::Enable {
-            mongo_db_uri,
-            mongo_db_name,
+) -> Result<Vec<StudentTotalScore>, SyncError> {
+    let results = AppState::get_scored_answers(app).ok_or(SyncError::IncorrectState)?;
+    let Options { grading, .. } = AppState::get_options(app);
+    let max_score = AppState::get_max_score(app).ok_or(SyncError::IncorrectState)?;
+    let question_rows = map_to_csv(results, grading.as_ref().map(|g| (g, max_score)));
+    Ok(map_to_db_scores(question_rows))
+}
+
+/// A filter on (student_id, subject_id, exam) and the document replacing whatever matches it.
+type Upsert = (Document, Document);
+
+fn total_score_upsert(exam: &str, row: &StudentTotalScore) -> Result<Upsert, SyncError> {
+    let mut document = bson::to_document(row)?;
+    document.insert("exam", exam);
+    Ok((
+        doc! { "student_id": &row.student_id, "subject_id": &row.subject_id, "exam": exam },
+        document,
+    ))
+}
+
+fn sheet_result_upsert(document: &SheetResultDocument) -> Result<Upsert, SyncError> {
+    Ok((
+        doc! {
+            "student_id": &document.record.student_id,
+            "subject_id": &document.record.subject_id,
+            "exam": &document.exam,
         },
-        ..
-    } = AppState::get_options(app)
-    {
-        //println!("MONGODB_URI = {:?}", std::env::var("MONGODB_URI"));
-        //println!("MY_DATABASE = {:?}", std::env::var("MY_DATABASE"));
-
-        tauri::async_runtime::block_on(async {
-            let options = ClientOptions::parse(&mongo_db_uri).await?;
-            let client = Client::with_options(options)?;
-
-            let collection = client
-                .database(&mongo_db_name)
-                .collection::<StudentTotalScore>("student_total_scores");
-
-            for row in rows {
-                let filter = doc! {
-                    "student_id": &row.student_id,
-                    "subject_id": &row.subject_id
-                };
-                collection.delete_one(filter.clone()).await?;
-                collection.insert_one(row).await?;
+        snake_case_keys(bson::to_document(document)?),
+    ))
+}
+
+/// `multipleMarks` becomes `multiple_marks`. Values are left alone.
+fn to_snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn snake_case_value(value: Bson) -> Bson {
+    match value {
+        Bson::Document(document) => Bson::Document(snake_case_keys(document)),
+        Bson::Array(values) => Bson::Array(values.into_iter().map(snake_case_value).collect()),
+        value => value,
+    }
+}
+
+/// Renames every key to snake_case, so the sheet records, which are camelCase like the JSON
+/// export, match the total scores. Every MongoDB document uses snake_case keys.
+fn snake_case_keys(document: Document) -> Document {
+    document
+        .into_iter()
+        .map(|(key, value)| (to_snake_case(&key), snake_case_value(value)))
+        .collect()
+}
+
+/// An unordered `update` command that upserts every document in one round trip. Plain commands
+/// are used instead of `Client::bulk_write`, which needs MongoDB 8.0.
+fn upsert_command(collection: &str, upserts: &[Upsert]) -> Document {
+    let updates = upserts
+        .iter()
+        .map(|(filter, replacement)| {
+            doc! {
+                "q": filter,
+                "u": replacement,
+                "upsert": true,
             }
+        })
+        .collect::<Vec<_>>();
+    doc! {
+        "update": collection,
+        "updates": updates,
+        "ordered": false,
+    }
+}
 
-            info!("Replaced total scores in MongoDB Atlas successfully");
-            Ok(())
+/// Reads the counts out of an `update` command reply, where `n` counts both matched and upserted
+/// documents.
+fn summarize_update_reply(reply: &Document) -> SyncCounts {
+    let count = |key: &str| match reply.get(key) {
+        Some(Bson::Int32(n)) => *n as usize,
+        Some(Bson::Int64(n)) => *n as usize,
+        _ => 0,
+    };
+    let inserted = reply.get_array("upserted").map_or(0, |u| u.len());
+    let errors = reply
+        .get_array("writeErrors")
+        .map(|errors| {
+            errors
+                .iter()
+                .filter_map(Bson::as_document)
+                .map(|e| e.get_str("errmsg").unwrap_or("unknown error").to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    SyncCounts {
+        inserted,
+        updated: count("n").saturating_sub(inserted),
+        failed: errors.len(),
+        errors,
+    }
+}
+
+/// Upserts a batch, or when `dry_run` is set, only counts which documents already exist.
+async fn upsert_batch(
+    database: &Database,
+    collection: &str,
+    upserts: &[Upsert],
+    dry_run: bool,
+) -> Result<SyncCounts, SyncError> {
+    if dry_run {
+        let filters = upserts
+            .iter()
+            .map(|(filter, _)| filter.clone())
+            .collect::<Vec<_>>();
+        let existing = database
+            .collection::<Document>(collection)
+            .count_documents(doc! { "$or": filters })
+            .await? as usize;
+        Ok(SyncCounts {
+            inserted: upserts.len().saturating_sub(existing),
+            updated: existing,
+            ..Default::default()
         })
     } else {
-        info!("You choose to not use MongoDB");
+        let reply = database
+            .run_command(upsert_command(collection, upserts))
+            .await?;
+        Ok(summarize_update_reply(&reply))
+    }
+}
+
+/// Uploads the annotated sheet, replacing the one from an earlier sync.
+async fn replace_image(bucket: &GridFsBucket, id: &str, bytes: &[u8]) -> Result<(), SyncError> {
+    let filename = format!("{id}.webp");
+    let id = Bson::String(id.to_string());
+    if bucket.find_one(doc! { "_id": &id }).await?.is_some() {
+        bucket.delete(id.clone()).await?;
+    }
+    let mut stream = bucket.open_upload_stream(filename).id(id).await?;
+    stream.write_all(bytes).await?;
+    stream.close().await?;
+    Ok(())
+}
+
+/// Upserts the total score of every scored sheet into MongoDB, reporting progress after every
+/// batch. With `include_details`, the item-level results go into `sheet_results` and the
+/// annotated images into the `sheet_images` GridFS bucket as well.
+async fn sync_to_mongodb<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    mongo_db_uri: &str,
+    mongo_db_name: &str,
+    exam: &str,
+    dry_run: bool,
+    include_details: bool,
+    channel: &Channel<DbSync>,
+) -> Result<SyncSummary, SyncError> {
+    let totals = student_totals_of(app)?
+        .iter()
+        .map(|row| total_score_upsert(exam, row))
+        .collect::<Result<Vec<_>, _>>()?;
+    let details = if include_details {
+        let results = AppState::get_scored_answers(app).ok_or(SyncError::IncorrectState)?;
+        let key = AppState::get_key(app).ok_or(SyncError::IncorrectState)?;
+        let weights = AppState::get_weights(app).ok_or(SyncError::IncorrectState)?;
+        results
+            .values()
+            .map(|(mat, sheet, result)| {
+                let document = sheet_result_document(exam, sheet, result, &key, &weights);
+                Ok((
+                    sheet_result_upsert(&document)?,
+                    document.image_id,
+                    mat_to_webp(mat)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, SyncError>>()?
+    } else {
+        vec![]
+    };
+    info!(
+        "Syncing {} total scores{} to MongoDB{}...",
+        totals.len(),
+        if include_details { " with details" } else { "" },
+        if dry_run { " (dry run)" } else { "" }
+    );
+
+    let client = Client::with_options(mongodb_options(mongo_db_uri).await?)?;
+    let database = client.database(mongo_db_name);
+
+    let total = totals.len() + details.len();
+    let mut summary = SyncSummary {
+        dry_run,
+        ..Default::default()
+    };
+    let mut synced = 0;
+    signal!(channel, DbSync::Progress { synced, total });
+    for batch in totals.chunks(SYNC_BATCH_SIZE) {
+        summary
+            .totals
+            .add(upsert_batch(&database, TOTAL_SCORES_COLLECTION, batch, dry_run).await?);
+        synced += batch.len();
+        signal!(channel, DbSync::Progress { synced, total });
+    }
+
+    if include_details {
+        let bucket = database.gridfs_bucket(
+            GridFsBucketOptions::builder()
+                .bucket_name(SHEET_IMAGES_BUCKET.to_string())
+                .build(),
+        );
+        let mut counts = SyncCounts::default();
+        for batch in details.chunks(SYNC_BATCH_SIZE) {
+            if !dry_run {
+                for (_, image_id, bytes) in batch {
+                    replace_image(&bucket, image_id, bytes).await?;
+                    summary.images_uploaded += 1;
+                }
+            }
+            let upserts = batch
+                .iter()
+                .map(|(upsert, _, _)| upsert.clone())
+                .collect::<Vec<_>>();
+            counts.add(upsert_batch(&database, SHEET_RESULTS_COLLECTION, &upserts, dry_run).await?);
+            synced += batch.len();
+            signal!(channel, DbSync::Progress { synced, total });
+        }
+        summary.sheet_results = Some(counts);
+    }
+
+    info!(
+        "Finished syncing to MongoDB: {} inserted, {} updated, {} failed",
+        summary.totals.inserted, summary.totals.updated, summary.totals.failed
+    );
+    Ok(summary)
+}
+
+/// Migrations of the PostgreSQL schema, applied in order. Each one that ran is recorded in
+/// `quikscore_migrations`, so new ones can only ever be appended.
+const POSTGRES_MIGRATIONS: &[&str] = &[r#"
+CREATE TABLE student_total_scores (
+    student_id TEXT NOT NULL,
+    subject_id TEXT NOT NULL,
+    exam TEXT NOT NULL,
+    subject_name TEXT NOT NULL,
+    student_name TEXT NOT NULL,
+    exam_room TEXT NOT NULL,
+    exam_seat TEXT NOT NULL,
+    total_score REAL NOT NULL,
+    percentage DOUBLE PRECISION,
+    curved_percentage DOUBLE PRECISION,
+    grade TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (student_id, subject_id, exam)
+);
+"#];
+
+/// Upserts a total score by (student_id, subject_id, exam). `xmax` is only 0 for rows that were
+/// just inserted, which tells inserts and updates apart.
+const POSTGRES_UPSERT: &str = "
+INSERT INTO student_total_scores (student_id, subject_id, exam, subject_name, student_name,
+    exam_room, exam_seat, total_score, percentage, curved_percentage, grade)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+ON CONFLICT (student_id, subject_id, exam) DO UPDATE SET
+    subject_name = EXCLUDED.subject_name,
+    student_name = EXCLUDED.student_name,
+    exam_room = EXCLUDED.exam_room,
+    exam_seat = EXCLUDED.exam_seat,
+    total_score = EXCLUDED.total_score,
+    percentage = EXCLUDED.percentage,
+    curved_percentage = EXCLUDED.curved_percentage,
+    grade = EXCLUDED.grade,
+    updated_at = now()
+RETURNING (xmax = 0) AS inserted";
+
+async fn mongodb_options(uri: &str) -> Result<ClientOptions, SyncError> {
+    let mut options = ClientOptions::parse(uri).await?;
+    options.server_selection_timeout = Some(CONNECT_TIMEOUT);
+    Ok(options)
+}
+
+async fn connect_postgres(
+    config: &tokio_postgres::Config,
+) -> Result<tokio_postgres::Client, SyncError> {
+    let tls = MakeTlsConnector::new(TlsConnector::new()?);
+    let (client, connection) = config
+        .clone()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .connect(tls)
+        .await?;
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = connection.await {
+            err_log!(&e);
+        }
+    });
+    Ok(client)
+}
+
+/// Brings the schema up to date.
+async fn migrate_postgres(client: &mut tokio_postgres::Client) -> Result<(), SyncError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS quikscore_migrations (version INTEGER PRIMARY KEY)",
+        )
+        .await?;
+    let version = client
+        .query_one(
+            "SELECT COALESCE(MAX(version), 0) FROM quikscore_migrations",
+            &[],
+        )
+        .await?
+        .get::<_, i32>(0);
+    for (i, migration) in POSTGRES_MIGRATIONS
+        .iter()
+        .enumerate()
+        .skip(version as usize)
+    {
+        let version = i as i32 + 1;
+        info!("Migrating PostgreSQL schema to version {version}");
+        let tx = client.transaction().await?;
+        tx.batch_execute(migration).await?;
+        tx.execute(
+            "INSERT INTO quikscore_migrations (version) VALUES ($1)",
+            &[&version],
+        )
+        .await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+/// Upserts a batch in one transaction, or when `dry_run` is set, only counts which rows already
+/// exist.
+async fn upsert_postgres_batch(
+    client: &mut tokio_postgres::Client,
+    exam: &str,
+    rows: &[StudentTotalScore],
+    dry_run: bool,
+) -> Result<SyncCounts, SyncError> {
+    if dry_run {
+        let student_ids = rows
+            .iter()
+            .map(|r| r.student_id.as_str())
+            .collect::<Vec<_>>();
+        let subject_ids = rows
+            .iter()
+            .map(|r| r.subject_id.as_str())
+            .collect::<Vec<_>>();
+        let existing = client
+            .query_one(
+                "SELECT COUNT(*) FROM student_total_scores
+                 WHERE exam = $1
+                   AND (student_id, subject_id) IN (SELECT * FROM UNNEST($2::text[], $3::text[]))",
+                &[&exam, &student_ids, &subject_ids],
+            )
+            .await?
+            .get::<_, i64>(0) as usize;
+        return Ok(SyncCounts {
+            inserted: rows.len().saturating_sub(existing),
+            updated: existing,
+            ..Default::default()
+        });
+    }
+
+    let tx = client.transaction().await?;
+    let upsert = tx.prepare(POSTGRES_UPSERT).await?;
+    let mut counts = SyncCounts::default();
+    for row in rows {
+        let inserted = tx
+            .query_one(
+                &upsert,
+                &[
+                    &row.student_id,
+                    &row.subject_id,
+                    &exam,
+                    &row.subject_name,
+                    &row.student_name,
+                    &row.exam_room,
+                    &row.exam_seat,
+                    &row.total_score,
+                    &row.percentage,
+                    &row.curved_percentage,
+                    &row.grade,
+                ],
+            )
+            .await?
+            .get::<_, bool>(0);
+        if inserted {
+            counts.inserted += 1;
+        } else {
+            counts.updated += 1;
+        }
+    }
+    tx.commit().await?;
+    Ok(counts)
+}
+
+/// Upserts the total score of every scored sheet into PostgreSQL under `exam`, reporting progress
+/// after every batch.
+async fn sync_to_postgres<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    url: &str,
+    exam: &str,
+    dry_run: bool,
+    channel: &Channel<DbSync>,
+) -> Result<SyncSummary, SyncError> {
+    let totals = student_totals_of(app)?;
+    info!(
+        "Syncing {} total scores of {exam} to PostgreSQL{}...",
+        totals.len(),
+        if dry_run { " (dry run)" } else { "" }
+    );
+
+    let mut client = connect_postgres(&url.parse()?).await?;
+    // a dry run must not touch the schema, so against a fresh database every row is an insert
+    let has_table = if dry_run {
+        client
+            .query_one(
+                "SELECT to_regclass('student_total_scores') IS NOT NULL",
+                &[],
+            )
+            .await?
+            .get::<_, bool>(0)
+    } else {
+        migrate_postgres(&mut client).await?;
+        true
+    };
+
+    let total = totals.len();
+    let mut summary = SyncSummary {
+        dry_run,
+        ..Default::default()
+    };
+    let mut synced = 0;
+    signal!(channel, DbSync::Progress { synced, total });
+    for batch in totals.chunks(SYNC_BATCH_SIZE) {
+        let counts = if has_table {
+            upsert_postgres_batch(&mut client, exam, batch, dry_run).await?
+        } else {
+            SyncCounts {
+                inserted: batch.len(),
+                ..Default::default()
+            }
+        };
+        summary.totals.add(counts);
+        synced += batch.len();
+        signal!(channel, DbSync::Progress { synced, total });
+    }
+
+    info!(
+        "Finished syncing to PostgreSQL: {} inserted, {} updated",
+        summary.totals.inserted, summary.totals.updated
+    );
+    Ok(summary)
+}
+
+/// Syncs the total scores to whichever database is set up in [`Options::storage`]. Both databases
+/// keep one total per student, subject and `exam`, so syncing another exam never overwrites
+/// earlier scores. `include_details` is only supported by MongoDB; PostgreSQL refuses it.
+pub async fn sync_scores<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    exam: &str,
+    dry_run: bool,
+    include_details: bool,
+    channel: &Channel<DbSync>,
+) -> Result<SyncSummary, SyncError> {
+    match AppState::get_options(app).storage {
+        Storage::Disable => Err(SyncError::Disabled),
+        Storage::MongoDb {
+            mongo_db_uri,
+            mongo_db_name,
+        } => {
+            sync_to_mongodb(
+                app,
+                &mongo_db_uri,
+                &mongo_db_name,
+                exam,
+                dry_run,
+                include_details,
+                channel,
+            )
+            .await
+        }
+        Storage::Postgres { .. } if include_details => Err(SyncError::DetailsUnsupported),
+        Storage::Postgres { url } => sync_to_postgres(app, &url, exam, dry_run, channel).await,
+    }
+}
+
+/// `_id` of the document written and removed again to check for write permission.
+const CONNECTION_PROBE_ID: &str = "quikscore_connection_test";
+
+/// Whether the database in `storage` can be synced to: the connection string parses, the server
+/// answers and the total scores collection or table can be written to.
+pub async fn test_connection(storage: &Storage) -> Result<(), ConnectionError> {
+    let unreachable = |e: &dyn std::error::Error| ConnectionError::Unreachable(e.to_string());
+    match storage {
+        Storage::Disable => Err(ConnectionError::Disabled),
+        Storage::MongoDb {
+            mongo_db_uri,
+            mongo_db_name,
+        } => {
+            let invalid = |e: &dyn std::error::Error| ConnectionError::InvalidUri(e.to_string());
+            let options = mongodb_options(mongo_db_uri)
+                .await
+                .map_err(|e| invalid(&e))?;
+            let client = Client::with_options(options).map_err(|e| invalid(&e))?;
+            let database = client.database(mongo_db_name);
+            database
+                .run_command(doc! { "ping": 1 })
+                .await
+                .map_err(|e| unreachable(&e))?;
+
+            let no_write = |e: mongodb::error::Error| ConnectionError::NoWritePermission {
+                target: format!("{mongo_db_name}.{TOTAL_SCORES_COLLECTION}"),
+                error: e.to_string(),
+            };
+            let collection = database.collection::<Document>(TOTAL_SCORES_COLLECTION);
+            let probe = doc! { "_id": CONNECTION_PROBE_ID };
+            collection
+                .replace_one(probe.clone(), probe.clone())
+                .upsert(true)
+                .await
+                .map_err(no_write)?;
+            collection.delete_one(probe).await.map_err(no_write)?;
+            Ok(())
+        }
+        Storage::Postgres { url } => {
+            let config = url
+                .parse::<tokio_postgres::Config>()
+                .map_err(|e| ConnectionError::InvalidUri(e.to_string()))?;
+            let client = connect_postgres(&config)
+                .await
+                .map_err(|e| unreachable(&e))?;
+            // the table is only created on the first sync, so until then creating it has to be
+            // allowed
+            let can_write = client
+                .query_one(
+                    "SELECT COALESCE(CASE
+                         WHEN to_regclass('student_total_scores') IS NULL
+                             THEN has_schema_privilege(current_schema(), 'CREATE')
+                         ELSE has_table_privilege('student_total_scores', 'INSERT, UPDATE')
+                     END, false)",
+                    &[],
+                )
+                .await
+                .map_err(|e| unreachable(&e))?
+                .get::<_, bool>(0);
+            if can_write {
+                Ok(())
+            } else {
+                Err(ConnectionError::NoWritePermission {
+                    target: "student_total_scores".to_string(),
+                    error: "permission denied".to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// Name of the file in the app data directory holding total scores that could not be synced.
+const SYNC_QUEUE_FILE: &str = "sync_queue.json";
+
+/// Total scores of an exam that could not be synced.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedSync {
+    pub exam: String,
+    pub rows: Vec<StudentTotalScore>,
+}
+
+/// Total scores waiting for the database to be reachable again, kept as JSON so they survive
+/// restarts. Only the rows are queued, never the connection settings.
+pub struct SyncQueue {
+    path: PathBuf,
+}
+
+impl SyncQueue {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The queue in the app data directory.
+    pub fn open_default<R: Runtime, A: Emitter<R> + Manager<R>>(
+        app: &A,
+    ) -> Result<Self, SyncError> {
+        let dir = app.path().app_data_dir()?;
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self::new(dir.join(SYNC_QUEUE_FILE)))
+    }
+
+    pub fn load(&self) -> Result<Vec<QueuedSync>, SyncError> {
+        match File::open(&self.path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Number of queued rows.
+    pub fn queued_rows(&self) -> Result<usize, SyncError> {
+        Ok(self.load()?.iter().map(|e| e.rows.len()).sum())
+    }
+
+    /// Replaces the queue. A temporary file is written first, so a crash can't leave it half
+    /// written.
+    fn store(&self, entries: &[QueuedSync]) -> Result<(), SyncError> {
+        if entries.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, entries)?;
+        writer.flush()?;
+        std::fs::rename(tmp, &self.path)?;
         Ok(())
     }
+
+    /// Queues the rows of `exam`, dropping queued rows of the same student and subject since the
+    /// newer scores win. Returns the number of queued rows.
+    pub fn push(&self, exam: String, rows: Vec<StudentTotalScore>) -> Result<usize, SyncError> {
+        let mut entries = self.load()?;
+        for entry in entries.iter_mut().filter(|e| e.exam == exam) {
+            entry.rows.retain(|queued| {
+                !rows
+                    .iter()
+                    .any(|r| r.student_id == queued.student_id && r.subject_id == queued.subject_id)
+            });
+        }
+        entries.retain(|e| !e.rows.is_empty());
+        entries.push(QueuedSync { exam, rows });
+        self.store(&entries)?;
+        Ok(entries.iter().map(|e| e.rows.len()).sum())
+    }
+}
+
+/// Upserts the total scores of one exam into whichever database is set up.
+async fn upsert_totals(
+    storage: &Storage,
+    exam: &str,
+    rows: &[StudentTotalScore],
+) -> Result<SyncCounts, SyncError> {
+    let mut counts = SyncCounts::default();
+    match storage {
+        Storage::Disable => return Err(SyncError::Disabled),
+        Storage::MongoDb {
+            mongo_db_uri,
+            mongo_db_name,
+        } => {
+            let client = Client::with_options(mongodb_options(mongo_db_uri).await?)?;
+            let database = client.database(mongo_db_name);
+            let upserts = rows
+                .iter()
+                .map(|row| total_score_upsert(exam, row))
+                .collect::<Result<Vec<_>, _>>()?;
+            for batch in upserts.chunks(SYNC_BATCH_SIZE) {
+                counts.add(upsert_batch(&database, TOTAL_SCORES_COLLECTION, batch, false).await?);
+            }
+        }
+        Storage::Postgres { url } => {
+            let mut client = connect_postgres(&url.parse()?).await?;
+            migrate_postgres(&mut client).await?;
+            for batch in rows.chunks(SYNC_BATCH_SIZE) {
+                counts.add(upsert_postgres_batch(&mut client, exam, batch, false).await?);
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Sends every queued total score, keeping whatever still can't be sent in the queue.
+pub async fn retry_sync_queue<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+) -> Result<SyncCounts, SyncError> {
+    let _lock = SYNC_QUEUE_LOCK.lock().await;
+    let queue = SyncQueue::open_default(app)?;
+    let entries = queue.load()?;
+    let mut counts = SyncCounts::default();
+    if entries.is_empty() {
+        return Ok(counts);
+    }
+    let Options { storage, .. } = AppState::get_options(app);
+    info!(
+        "Retrying {} queued total scores...",
+        entries.iter().map(|e| e.rows.len()).sum::<usize>()
+    );
+    let mut kept = vec![];
+    let mut entries = entries.into_iter();
+    while let Some(entry) = entries.next() {
+        match upsert_totals(&storage, &entry.exam, &entry.rows).await {
+            Ok(sent) => {
+                // rows the database refused have to be sent again; upserts are idempotent, so
+                // the rest of the entry going along with them does no harm
+                if sent.failed > 0 {
+                    kept.push(entry);
+                }
+                counts.add(sent);
+            }
+            Err(e) => {
+                kept.push(entry);
+                kept.extend(entries);
+                queue.store(&kept)?;
+                return Err(e);
+            }
+        }
+    }
+    queue.store(&kept)?;
+    info!(
+        "Sent queued total scores: {} inserted, {} updated, {} failed",
+        counts.inserted, counts.updated, counts.failed
+    );
+    Ok(counts)
+}
+
+/// Retries the queue left over from an earlier run, if a database is set up.
+pub async fn retry_sync_queue_on_launch<R: Runtime, A: Emitter<R> + Manager<R>>(app: &A) {
+    match retry_sync_queue(app).await {
+        Ok(_) => {}
+        Err(SyncError::Disabled) => {
+            info!("Queued total scores will be sent once a database is set up")
+        }
+        Err(e) => err_log!(&e),
+    }
+}
+
+/// Queues the current total scores of `exam`, returning the number of queued rows.
+async fn queue_totals<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    exam: String,
+) -> Result<usize, SyncError> {
+    let rows = student_totals_of(app)?;
+    let _lock = SYNC_QUEUE_LOCK.lock().await;
+    SyncQueue::open_default(app)?.push(exam, rows)
+}
+
+/// Syncs like [`sync_scores`], but when the database fails the total scores are queued on disk
+/// instead of being lost. Upserts are idempotent, so rows that made it before the failure are
+/// simply sent again.
+pub async fn sync_database_wrapper<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    exam: String,
+    dry_run: bool,
+    include_details: bool,
+    channel: Channel<DbSync>,
+) {
+    match sync_scores(app, &exam, dry_run, include_details, &channel).await {
+        Ok(summary) => signal!(channel, DbSync::Done { summary }),
+        Err(e) if !dry_run && e.is_database_failure() => {
+            err_log!(&e);
+            match queue_totals(app, exam).await {
+                Ok(queued) => {
+                    info!("Queued total scores, {queued} rows are waiting to be synced");
+                    signal!(
+                        channel,
+                        DbSync::Queued {
+                            queued,
+                            error: format!("{e}")
+                        }
+                    )
+                }
+                Err(queue_error) => {
+                    err_log!(&queue_error);
+                    signal!(
+                        channel,
+                        DbSync::Error {
+                            error: format!(
+                                "Error whilst trying to sync: {e}. \
+                                 The scores could not be queued either: {queue_error}"
+                            )
+                        }
+                    )
+                }
+            }
+        }
+        Err(e) => {
+            err_log!(&e);
+            signal!(
+                channel,
+                DbSync::Error {
+                    error: format!("Error whilst trying to sync: {e}")
+                }
+            )
+        }
+    }
+}
+
+/// Migrations of the SQLite history, applied in order. `PRAGMA user_version` records how many
+/// have run, so new ones can only ever be appended.
+const SQLITE_MIGRATIONS: &[&str] = &[r#"
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY,
+    exam TEXT NOT NULL,
+    subject_id TEXT NOT NULL,
+    max_score INTEGER NOT NULL,
+    weights_version TEXT NOT NULL,
+    saved_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE sheets (
+    id INTEGER PRIMARY KEY,
+    session_id INTEGER NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    student_id TEXT NOT NULL,
+    subject_name TEXT,
+    student_name TEXT,
+    exam_room TEXT,
+    exam_seat TEXT,
+    score INTEGER NOT NULL,
+    correct INTEGER NOT NULL,
+    incorrect INTEGER NOT NULL
+);
+CREATE INDEX sheets_student_id ON sheets(student_id);
+CREATE TABLE question_scores (
+    sheet_id INTEGER NOT NULL REFERENCES sheets(id) ON DELETE CASCADE,
+    question INTEGER NOT NULL,
+    verdict TEXT NOT NULL,
+    points INTEGER NOT NULL,
+    weight INTEGER NOT NULL,
+    PRIMARY KEY (sheet_id, question)
+);
+CREATE TABLE answers (
+    sheet_id INTEGER NOT NULL REFERENCES sheets(id) ON DELETE CASCADE,
+    question INTEGER NOT NULL,
+    row TEXT NOT NULL,
+    answer TEXT,
+    key TEXT,
+    multiple_marks INTEGER NOT NULL,
+    verdict TEXT NOT NULL,
+    PRIMARY KEY (sheet_id, question, row)
+);
+"#];
+/// Name of the history database in the app data directory.
+const HISTORY_FILE: &str = "history.sqlite3";
+
+/// Everything scored in one go, as it is kept in the results history.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoringSession {
+    /// Name the teacher gave the exam, e.g. `"Midterm 1/2025"`.
+    pub exam: String,
+    pub subject_id: String,
+    pub max_score: u32,
+    /// See [`weights_version`].
+    pub weights_version: String,
+    pub sheets: Vec<SheetRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSummary {
+    pub id: i64,
+    pub exam: String,
+    pub subject_id: String,
+    pub max_score: u32,
+    /// UTC, as `YYYY-MM-DD HH:MM:SS`.
+    pub saved_at: String,
+    pub sheets: u32,
+    pub mean_score: f64,
+}
+
+/// How a student did in one session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub session_id: i64,
+    pub exam: String,
+    pub subject_id: String,
+    pub saved_at: String,
+    pub score: u32,
+    pub max_score: u32,
+    pub correct: u32,
+    pub incorrect: u32,
+}
+
+fn verdict_name(verdict: CheckedAnswer) -> &'static str {
+    match verdict {
+        CheckedAnswer::Correct => "correct",
+        CheckedAnswer::Incorrect => "incorrect",
+        CheckedAnswer::Missing => "missing",
+        CheckedAnswer::NotCounted => "notCounted",
+    }
+}
+
+/// Somewhere scoring sessions are kept so they can be looked up across exams. Every call blocks,
+/// see [`with_history`].
+pub trait StorageBackend {
+    /// Stores the session with all of its sheets, answers and scores, returning its ID.
+    fn save_session(&mut self, session: &ScoringSession) -> Result<i64, StorageError>;
+    /// Every stored session, newest first.
+    fn sessions(&self) -> Result<Vec<SessionSummary>, StorageError>;
+    /// Every stored result of a student, oldest first.
+    fn student_history(&self, student_id: &str) -> Result<Vec<HistoryEntry>, StorageError>;
+}
+
+/// Results history in a single SQLite file, for teachers without a database server.
+pub struct SqliteHistory {
+    conn: rusqlite::Connection,
+}
+
+impl SqliteHistory {
+    /// Opens the database at `path`, creating it and bringing its schema up to date if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let conn = rusqlite::Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let version =
+            conn.pragma_query_value(None, "user_version", |row| row.get::<_, usize>(0))?;
+        for (i, migration) in SQLITE_MIGRATIONS.iter().enumerate().skip(version) {
+            info!("Migrating results history to version {}", i + 1);
+            conn.execute_batch(migration)?;
+            conn.pragma_update(None, "user_version", i + 1)?;
+        }
+        Ok(Self { conn })
+    }
+}
+
+impl StorageBackend for SqliteHistory {
+    fn save_session(&mut self, session: &ScoringSession) -> Result<i64, StorageError> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO sessions (exam, subject_id, max_score, weights_version)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                session.exam,
+                session.subject_id,
+                session.max_score,
+                session.weights_version
+            ],
+        )?;
+        let session_id = tx.last_insert_rowid();
+        {
+            let mut insert_sheet = tx.prepare(
+                "INSERT INTO sheets (session_id, student_id, subject_name, student_name, exam_room,
+                     exam_seat, score, correct, incorrect)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            let mut insert_question = tx.prepare(
+                "INSERT INTO question_scores (sheet_id, question, verdict, points, weight)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            let mut insert_answer = tx.prepare(
+                "INSERT INTO answers (sheet_id, question, row, answer, key, multiple_marks, verdict)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for sheet in &session.sheets {
+                insert_sheet.execute(params![
+                    session_id,
+                    sheet.student_id,
+                    sheet.subject_name,
+                    sheet.student_name,
+                    sheet.exam_room,
+                    sheet.exam_seat,
+                    sheet.score,
+                    sheet.correct,
+                    sheet.incorrect
+                ])?;
+                let sheet_id = tx.last_insert_rowid();
+                for question in &sheet.questions {
+                    insert_question.execute(params![
+                        sheet_id,
+                        question.question,
+                        verdict_name(question.verdict),
+                        question.points,
+                        question.weight
+                    ])?;
+                    for row in &question.rows {
+                        insert_answer.execute(params![
+                            sheet_id,
+                            question.question,
+                            row.row.to_string(),
+                            row.answer.as_ref().map(|a| &a.text),
+                            row.key.as_ref().map(|k| &k.text),
+                            row.multiple_marks,
+                            verdict_name(row.verdict)
+                        ])?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(session_id)
+    }
+
+    fn sessions(&self) -> Result<Vec<SessionSummary>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.id, s.exam, s.subject_id, s.max_score, s.saved_at, COUNT(sh.id),
+                 COALESCE(AVG(sh.score), 0.0)
+             FROM sessions s LEFT JOIN sheets sh ON sh.session_id = s.id
+             GROUP BY s.id
+             ORDER BY s.saved_at DESC, s.id DESC",
+        )?;
+        let sessions = stmt
+            .query_map([], |row| {
+                Ok(SessionSummary {
+                    id: row.get(0)?,
+                    exam: row.get(1)?,
+                    subject_id: row.get(2)?,
+                    max_score: row.get(3)?,
+                    saved_at: row.get(4)?,
+                    sheets: row.get(5)?,
+                    mean_score: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sessions)
+    }
+
+    fn student_history(&self, student_id: &str) -> Result<Vec<HistoryEntry>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.id, s.exam, s.subject_id, s.saved_at, sh.score, s.max_score, sh.correct,
+                 sh.incorrect
+             FROM sheets sh JOIN sessions s ON sh.session_id = s.id
+             WHERE sh.student_id = ?1
+             ORDER BY s.saved_at, s.id",
+        )?;
+        let history = stmt
+            .query_map([student_id], |row| {
+                Ok(HistoryEntry {
+                    session_id: row.get(0)?,
+                    exam: row.get(1)?,
+                    subject_id: row.get(2)?,
+                    saved_at: row.get(3)?,
+                    score: row.get(4)?,
+                    max_score: row.get(5)?,
+                    correct: row.get(6)?,
+                    incorrect: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(history)
+    }
+}
+
+/// The current scoring results as a session of `exam`.
+pub fn scoring_session_of<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    exam: String,
+) -> Result<ScoringSession, StorageError> {
+    let results = AppState::get_scored_answers(app).ok_or(StorageError::IncorrectState)?;
+    let key = AppState::get_key(app).ok_or(StorageError::IncorrectState)?;
+    let weights = AppState::get_weights(app).ok_or(StorageError::IncorrectState)?;
+    let max_score = AppState::get_max_score(app).ok_or(StorageError::IncorrectState)?;
+    let mut sheets = results
+        .values()
+        .map(|(_, sheet, result)| sheet_record(sheet, result, &key, &weights))
+        .collect::<Vec<_>>();
+    sheets.sort_by(|a, b| a.student_id.cmp(&b.student_id));
+    Ok(ScoringSession {
+        exam,
+        weights_version: weights
+            .weights
+            .get(&key.subject_id)
+            .map(|(w, _)| weights_version(w))
+            .unwrap_or_default(),
+        subject_id: key.subject_id,
+        max_score,
+        sheets,
+    })
+}
+
+/// Opens `history.sqlite3` in the app data directory and runs `f` on it. rusqlite blocks, so this
+/// happens on a blocking thread instead of the async runtime.
+async fn with_history<R, A, T, F>(app: &A, f: F) -> Result<T, StorageError>
+where
+    R: Runtime,
+    A: Emitter<R> + Manager<R>,
+    T: Send + 'static,
+    F: FnOnce(&mut dyn StorageBackend) -> Result<T, StorageError> + Send + 'static,
+{
+    let dir = app.path().app_data_dir()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        std::fs::create_dir_all(&dir)?;
+        f(&mut SqliteHistory::open(dir.join(HISTORY_FILE))?)
+    })
+    .await
+    .map_err(StorageError::Task)?
+}
+
+/// Saves the current scoring results to the local results history.
+pub async fn save_to_history<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    exam: String,
+) -> Result<i64, StorageError> {
+    let session = scoring_session_of(app, exam)?;
+    info!(
+        "Saving {} sheets of {} to the results history...",
+        session.sheets.len(),
+        session.exam
+    );
+    let id = with_history(app, move |history| history.save_session(&session)).await?;
+    info!("Saved session {id} to the results history");
+    Ok(id)
+}
+
+/// Every session in the local results history.
+pub async fn history_sessions<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+) -> Result<Vec<SessionSummary>, StorageError> {
+    with_history(app, |history| history.sessions()).await
+}
+
+/// Every result of a student in the local results history, across exams.
+pub async fn student_history<R: Runtime, A: Emitter<R> + Manager<R>>(
+    app: &A,
+    student_id: String,
+) -> Result<Vec<HistoryEntry>, StorageError> {
+    with_history(app, move |history| history.student_history(&student_id)).await
 }
 
 #[cfg(test)]
@@ -258,7 +1922,7 @@ mod unit_tests {
 
     use crate::{
         scoring::{CheckedAnswer, CheckedQuestionGroup},
-        state::QuestionGroup,
+        state::{unit_tests::SheetBuilder, QuestionGroup},
     };
 
     use super::*;
@@ -274,6 +1938,7 @@ mod unit_tests {
             exam_seat: "A03".to_string(),
             questions: (0..36).map(|_| "1".to_string()).collect(),
             total_score: "36".to_string(),
+            grade: None,
         };
         let mut writer = csv::Writer::from_writer(vec![]);
         writer.serialize(scores).unwrap();
@@ -287,6 +1952,32 @@ mod unit_tests {
         )
     }
 
+    #[test]
+    fn test_question_score_row_serializer_with_grade() {
+        let scores = QuestionScoreRow {
+            subject_id: "10".to_string(),
+            student_id: "65010003".to_string(),
+            subject_name: "Mathematics".to_string(),
+            student_name: "Marcia Cole".to_string(),
+            exam_room: "608".to_string(),
+            exam_seat: "A03".to_string(),
+            questions: (0..36).map(|_| "0".to_string()).collect(),
+            total_score: "0".to_string(),
+            grade: Some(Grade {
+                percentage: 0.0,
+                curved_percentage: None,
+                grade: "F".to_string(),
+            }),
+        };
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(scores).unwrap();
+
+        let result = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let (header, row) = result.split_once('\n').unwrap();
+        assert!(header.ends_with(",35,36,total_score,percentage,curved_percentage,grade"));
+        assert!(row.ends_with(",0,0,0.00,,F\n"));
+    }
+
     #[test]
     fn test_map_to_csv_vec() {
         let mut map = HashMap::new();
@@ -294,15 +1985,12 @@ mod unit_tests {
             "65010003".into(),
             (
                 Mat::default(),
-                AnswerSheet {
-                    subject_id: "10".to_string(),
-                    student_id: "65010003".to_string(),
-                    subject_name: Some("Mathematics".to_string()),
-                    student_name: Some("Marcia Cole".to_string()),
-                    exam_room: Some("608".to_string()),
-                    exam_seat: Some("A03".to_string()),
-                    answers: array::from_fn(|_| QuestionGroup::default()),
-                },
+                SheetBuilder::default()
+                    .subject_name("Mathematics")
+                    .student_name("Marcia Cole")
+                    .exam_room("608")
+                    .exam_seat("A03")
+                    .build(),
                 AnswerSheetResult {
                     correct: 36,
                     incorrect: 0,
@@ -323,7 +2011,7 @@ mod unit_tests {
             ),
         );
 
-        let rows = map_to_csv(map);
+        let rows = map_to_csv(map.clone(), None);
         assert_eq!(rows.len(), 1);
 
         let row = &rows[0];
@@ -336,6 +2024,215 @@ mod unit_tests {
         assert_eq!(row.questions.len(), 36);
         assert!(row.questions.iter().all(|q| q == "1"));
         assert_eq!(row.total_score, "36");
+        assert_eq!(row.grade, None);
+
+        let rows = map_to_csv(map, Some((&GradingScale::pass_fail(50.0), 40)));
+        let grade = rows[0].grade.as_ref().unwrap();
+        assert_eq!(grade.percentage, 90.0);
+        assert_eq!(grade.grade, "Pass");
+    }
+
+    #[test]
+    fn test_build_workbook() {
+        let rows = vec![QuestionScoreRow {
+            subject_id: "10".to_string(),
+            student_id: "65010003".to_string(),
+            subject_name: "Mathematics".to_string(),
+            student_name: "Marcia Cole".to_string(),
+            exam_room: "608".to_string(),
+            exam_seat: "A03".to_string(),
+            questions: (0..36).map(|_| "1".to_string()).collect(),
+            total_score: "36".to_string(),
+            grade: None,
+        }];
+        let stats = statistics::exam_statistics([], 36);
+        let items = analysis::analyze_items([]);
+
+        let mut workbook = build_workbook(&rows, &stats, &items).unwrap();
+        let buffer = workbook.save_to_buffer().unwrap();
+        // XLSX files are zip archives
+        assert_eq!(&buffer[0..2], b"PK");
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(&rows[0]).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv.lines().next(),
+            Some(scores_headers(false).join(",").as_str())
+        );
+    }
+
+    fn record_for_test() -> SheetRecord {
+        let key = AnswerKeySheet {
+            subject_id: "10".to_string(),
+            answers: array::from_fn(|_| QuestionGroup {
+                A: Some(Answer::Both(NumberType::Minus, 3)),
+                ..Default::default()
+            }),
+        };
+        let mut multi_marked = [[false; 5]; 36];
+        multi_marked[1][0] = true;
+        let sheet = SheetBuilder::default()
+            .subject_name("Mathematics")
+            .answers(array::from_fn(|q| QuestionGroup {
+                A: (q == 0).then_some(Answer::Both(NumberType::Minus, 3)),
+                ..Default::default()
+            }))
+            .multi_marked(multi_marked)
+            .build();
+        let weights = ScoreWeights {
+            weights: HashMap::from([("10".to_string(), (vec![2; 36], 72))]),
+        };
+        let result = sheet.score(&key, &weights.weights["10"].0);
+        sheet_record(&sheet, &result, &key, &weights)
+    }
+
+    #[test]
+    fn test_sheet_record() {
+        let record = record_for_test();
+        assert_eq!(record.schema_version, JSON_SCHEMA_VERSION);
+        assert_eq!(record.score, 2);
+        assert_eq!(record.max_score, 72);
+        assert_eq!(record.questions.len(), 36);
+
+        let first = &record.questions[0];
+        assert_eq!(first.verdict, CheckedAnswer::Correct);
+        assert_eq!(first.points, 2);
+        assert_eq!(first.weight, 2);
+        assert_eq!(
+            first.rows[0].answer,
+            Some(AnswerRecord {
+                sign: Some(NumberType::Minus),
+                digit: Some(3),
+                text: "-3".to_string()
+            })
+        );
+        assert_eq!(first.rows[1].verdict, CheckedAnswer::NotCounted);
+
+        let second = &record.questions[1];
+        assert_eq!(second.verdict, CheckedAnswer::Missing);
+        assert!(second.rows[0].multiple_marks);
+        assert_eq!(second.rows[0].answer, None);
+    }
+
+    #[test]
+    fn test_write_json_lines() {
+        let records = [record_for_test(), record_for_test()];
+        let mut buf = vec![];
+        write_json(&mut buf, &records, true).unwrap();
+
+        let out = String::from_utf8(buf).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["studentId"], "65010003");
+        assert_eq!(value["questions"][0]["rows"][0]["key"]["sign"], "minus");
+        assert_eq!(value["questions"][1]["rows"][0]["multipleMarks"], true);
+
+        let mut buf = vec![];
+        write_json(&mut buf, &records, false).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 2);
+    }
+
+    fn total_score(student_id: &str) -> StudentTotalScore {
+        StudentTotalScore {
+            subject_id: "10".to_string(),
+            student_id: student_id.to_string(),
+            subject_name: "Mathematics".to_string(),
+            student_name: "Marcia Cole".to_string(),
+            exam_room: "608".to_string(),
+            exam_seat: "A03".to_string(),
+            total_score: 36.0,
+            percentage: None,
+            curved_percentage: None,
+            grade: None,
+        }
+    }
+
+    #[test]
+    fn test_upsert_command() {
+        let upserts = [total_score("65010003"), total_score("65010009")]
+            .iter()
+            .map(|row| total_score_upsert("midterm", row))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let command = upsert_command(TOTAL_SCORES_COLLECTION, &upserts);
+
+        assert_eq!(command.get_str("update").unwrap(), TOTAL_SCORES_COLLECTION);
+        assert!(!command.get_bool("ordered").unwrap());
+        let updates = command.get_array("updates").unwrap();
+        assert_eq!(updates.len(), 2);
+        let update = updates[1].as_document().unwrap();
+        assert_eq!(
+            update.get_document("q").unwrap(),
+            &doc! { "student_id": "65010009", "subject_id": "10", "exam": "midterm" }
+        );
+        assert!(update.get_bool("upsert").unwrap());
+        let replacement = update.get_document("u").unwrap();
+        assert_eq!(replacement.get_str("student_name").unwrap(), "Marcia Cole");
+        assert_eq!(replacement.get_str("exam").unwrap(), "midterm");
+        assert!(!replacement.contains_key("grade"));
+    }
+
+    #[test]
+    fn test_sheet_result_upsert_is_snake_case() {
+        let document = SheetResultDocument {
+            record: record_for_test(),
+            exam: "midterm".to_string(),
+            weights_version: "e3b0c44298fc1c14".to_string(),
+            image_id: "10_65010003".to_string(),
+        };
+        let (filter, replacement) = sheet_result_upsert(&document).unwrap();
+
+        assert_eq!(
+            filter,
+            doc! { "student_id": "65010003", "subject_id": "10", "exam": "midterm" }
+        );
+        assert_eq!(replacement.get_str("exam").unwrap(), "midterm");
+        assert_eq!(replacement.get_str("student_id").unwrap(), "65010003");
+        assert_eq!(
+            replacement.get_str("weights_version").unwrap(),
+            "e3b0c44298fc1c14"
+        );
+        assert_eq!(replacement.get_str("image_id").unwrap(), "10_65010003");
+        assert!(replacement.contains_key("schema_version"));
+        assert!(!replacement.contains_key("studentId"));
+        let question = replacement.get_array("questions").unwrap()[0]
+            .as_document()
+            .unwrap();
+        let row = question.get_array("rows").unwrap()[0]
+            .as_document()
+            .unwrap();
+        assert!(row.contains_key("multiple_marks"));
+        assert!(!row.contains_key("multipleMarks"));
+    }
+
+    #[test]
+    fn test_summarize_update_reply() {
+        let reply = doc! {
+            "n": 3,
+            "nModified": 1,
+            "upserted": [{ "index": 2, "_id": 1 }],
+            "writeErrors": [{ "index": 3, "code": 11000, "errmsg": "duplicate key" }],
+            "ok": 1.0,
+        };
+        assert_eq!(
+            summarize_update_reply(&reply),
+            SyncCounts {
+                inserted: 1,
+                updated: 2,
+                failed: 1,
+                errors: vec!["duplicate key".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_weights_version() {
+        assert_eq!(weights_version(&[1; 36]), weights_version(&[1; 36]));
+        assert_ne!(weights_version(&[1; 36]), weights_version(&[2; 36]));
+        assert_eq!(weights_version(&[]), "e3b0c44298fc1c14");
     }
 
     // #[test]
@@ -387,4 +2284,110 @@ mod unit_tests {
     //
     //     todo!()
     // }
+
+    #[test]
+    fn test_sync_queue() {
+        let path =
+            std::env::temp_dir().join(format!("quikscore_queue_{}.json", std::process::id()));
+        _ = std::fs::remove_file(&path);
+        let queue = SyncQueue::new(path.clone());
+        assert_eq!(queue.queued_rows().unwrap(), 0);
+
+        let queued = queue
+            .push(
+                "Midterm".to_string(),
+                vec![total_score("65010003"), total_score("65010009")],
+            )
+            .unwrap();
+        assert_eq!(queued, 2);
+        // a newer score of the same student replaces the queued one
+        let mut newer = total_score("65010003");
+        newer.total_score = 12.0;
+        assert_eq!(queue.push("Midterm".to_string(), vec![newer]).unwrap(), 2);
+        assert_eq!(
+            queue
+                .push("Final".to_string(), vec![total_score("65010003")])
+                .unwrap(),
+            3
+        );
+
+        let entries = SyncQueue::new(path.clone()).load().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].rows[0].student_id, "65010009");
+        assert_eq!(entries[1].rows[0].total_score, 12.0);
+        assert_eq!(entries[2].exam, "Final");
+
+        queue.store(&[]).unwrap();
+        assert!(!path.exists());
+        assert_eq!(queue.queued_rows().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_storage_from_frontend() {
+        let storage = serde_json::from_str::<Storage>(
+            r#"{ "kind": "postgres", "url": "postgres://localhost/warehouse" }"#,
+        )
+        .unwrap();
+        assert!(
+            matches!(storage, Storage::Postgres { url } if url == "postgres://localhost/warehouse")
+        );
+        let storage = serde_json::from_str::<Storage>(
+            r#"{ "kind": "mongoDb", "mongoDbUri": "mongodb://localhost", "mongoDbName": "scores" }"#,
+        )
+        .unwrap();
+        assert!(
+            matches!(storage, Storage::MongoDb { mongo_db_name, .. } if mongo_db_name == "scores")
+        );
+    }
+
+    #[test]
+    fn test_sqlite_history() {
+        let path =
+            std::env::temp_dir().join(format!("quikscore_test_{}.sqlite3", std::process::id()));
+        _ = std::fs::remove_file(&path);
+        let session = |exam: &str| ScoringSession {
+            exam: exam.to_string(),
+            subject_id: "10".to_string(),
+            max_score: 72,
+            weights_version: weights_version(&[2; 36]),
+            sheets: vec![record_for_test()],
+        };
+
+        let mut history = SqliteHistory::open(&path).unwrap();
+        let midterm = history.save_session(&session("Midterm")).unwrap();
+        drop(history);
+        // reopening must not run the migrations again
+        let mut history = SqliteHistory::open(&path).unwrap();
+        let final_exam = history.save_session(&session("Final")).unwrap();
+
+        let sessions = history.sessions().unwrap();
+        assert_eq!(
+            sessions.iter().map(|s| s.id).collect::<Vec<_>>(),
+            [final_exam, midterm]
+        );
+        assert_eq!(sessions[0].sheets, 1);
+        assert_eq!(sessions[0].mean_score, 2.0);
+
+        let entries = history.student_history("65010003").unwrap();
+        assert_eq!(
+            entries.iter().map(|h| h.exam.as_str()).collect::<Vec<_>>(),
+            ["Midterm", "Final"]
+        );
+        assert_eq!(entries[0].score, 2);
+        assert_eq!(entries[0].max_score, 72);
+        assert!(history.student_history("65010009").unwrap().is_empty());
+
+        let (answers, multiple_marks) = history
+            .conn
+            .query_row(
+                "SELECT COUNT(*), SUM(multiple_marks) FROM answers",
+                [],
+                |row| Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?)),
+            )
+            .unwrap();
+        assert_eq!(answers, 2 * 36 * 5);
+        assert_eq!(multiple_marks, 2);
+        drop(history);
+        _ = std::fs::remove_file(&path);
+    }
 }
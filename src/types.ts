@@ -9,11 +9,22 @@ export type ModelDownload =
   | {
       event: "progress";
       data: {
+        model: string;
         progress: number;
-        total: number;
+        total: number | null;
       };
     }
   | { event: "success" };
+export type InstalledModel = {
+  name: string;
+  size: number;
+  pinned: boolean;
+  hash: "unchecked" | "verified" | "mismatch" | "unknown";
+};
+export type ModelInstall =
+  | { event: "cancelled" }
+  | { event: "done"; data: { installed: string[] } }
+  | { event: "error"; data: { error: string } };
 export type KeyUpload =
   | {
       event: "cancelled";
@@ -70,6 +81,22 @@ export type AnswerUpload =
       data: { error: string };
     };
 
+export type IdReview = {
+  reasons: ("bubbleOcrMismatch" | "notOnRoster" | "invalidLength" | "nearRosterId")[];
+  bubbled: string;
+  written: string | null;
+  chosen: string;
+  candidates: { studentId: string; score: number }[];
+};
+
+export type OcrField =
+  | "studentName"
+  | "subjectName"
+  | "examRoom"
+  | "examSeat"
+  | "subjectId"
+  | "studentId";
+
 export type AnswerScoreResult =
   | {
       result: "ok";
@@ -78,6 +105,8 @@ export type AnswerScoreResult =
         studentName: string | undefined;
         examRoom: string | undefined;
         examSeat: string | undefined;
+        idReview: IdReview | null;
+        lowConfidence: OcrField[];
         bytes: number[];
         score: number;
         maxScore: number;
@@ -98,6 +127,8 @@ export type BlobbedAnswerScoreResult =
         studentName: string | undefined;
         examRoom: string | undefined;
         examSeat: string | undefined;
+        idReview: IdReview | null;
+        lowConfidence: OcrField[];
         blobUrl: string;
         score: number;
         maxScore: number;